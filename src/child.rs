@@ -0,0 +1,620 @@
+//! Interactive and long-lived remote processes, see [`Client::spawn`].
+//!
+//! [`Client::spawn`]: crate::Client::spawn
+
+use crate::client::{CommandExit, SessionChannel};
+use crate::prompt::{PromptHandler, PromptWatcher};
+use crate::pty::PtyResizer;
+use crate::record::Recording;
+use russh::client::Msg;
+use russh::{ChannelWriteHalf, Sig};
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::mpsc;
+
+/// Number of output chunks buffered ahead of the reader of a [`ChildOutput`].
+const CHILD_OUTPUT_BUFFER: usize = 32;
+
+/// A remote process started with [`Client::spawn`], modeled on
+/// `tokio::process::Child`.
+///
+/// Its stdin, stdout and stderr are available as separate handles, so they can
+/// be driven concurrently. Dropping the child doesn't stop the process, use
+/// [`kill`](RemoteChild::kill) for that.
+///
+/// [`Client::spawn`]: crate::Client::spawn
+pub struct RemoteChild {
+    channel: Arc<ChannelWriteHalf<Msg>>,
+    stdin: Option<ChildStdin>,
+    stdout: Option<ChildOutput>,
+    stderr: Option<ChildOutput>,
+    exit: CommandExit,
+    exit_status: Option<ExitStatus>,
+    recording: Option<Arc<Mutex<Recording>>>, // shared with the output task and stdin
+}
+
+impl RemoteChild {
+    /// Start reading the output of the command running in `channel`. With
+    /// `stdin` data, it is sent followed by EOF and no stdin handle is provided.
+    /// With `allow_missing_exit_status`, a channel closing without exit status
+    /// ends the command with [`ExitStatus::Unknown`]. The output, input and
+    /// resizes go to `recording`, which is finished with the exit status.
+    pub(crate) async fn new(
+        channel: SessionChannel,
+        stdin: Option<&[u8]>,
+        allow_missing_exit_status: bool,
+        prompt_handler: Option<Arc<dyn PromptHandler>>,
+        recording: Option<Recording>,
+    ) -> Result<Self, crate::Error> {
+        let recording = recording.map(|recording| Arc::new(Mutex::new(recording)));
+        let task_recording = recording.clone();
+        let (channel, permit) = channel.into_parts();
+        let (mut read_half, write_half) = channel.split();
+        let channel = Arc::new(write_half);
+        let mut watcher =
+            prompt_handler.map(|handler| (PromptWatcher::new(handler), channel.clone()));
+        let (stdout_sender, stdout_receiver) = mpsc::channel(CHILD_OUTPUT_BUFFER);
+        let (stderr_sender, stderr_receiver) = mpsc::channel(CHILD_OUTPUT_BUFFER);
+        let (exit_sender, exit_receiver) = tokio::sync::oneshot::channel();
+
+        tokio::spawn(async move {
+            // The channel counts against the limit until it closed.
+            let _permit = permit;
+            let mut exit_sender = Some(exit_sender);
+            let record = |event: &dyn Fn(&mut Recording)| {
+                if let Some(recording) = &task_recording {
+                    event(&mut recording.lock().unwrap());
+                }
+            };
+            while let Some(msg) = read_half.wait().await {
+                // Dropped handles only discard their output.
+                match msg {
+                    russh::ChannelMsg::Data { data } => {
+                        record(&|recording| recording.output(&data));
+                        if let Some((watcher, channel)) = &mut watcher {
+                            if let Some(answer) = watcher.push(&data) {
+                                let _ = channel.data(&answer[..]).await;
+                            }
+                        }
+                        let _ = stdout_sender.send(data.to_vec()).await;
+                    }
+                    // Extended data of type 1 is stderr, RFC 4254 section 5.2.
+                    russh::ChannelMsg::ExtendedData { data, ext: 1 } => {
+                        record(&|recording| recording.output(&data));
+                        let _ = stderr_sender.send(data.to_vec()).await;
+                    }
+                    msg => {
+                        if let Some(status) = ExitStatus::from_msg(&msg) {
+                            // Finished before `wait` returns, so the recording is complete then.
+                            record(&|recording| recording.finish(&status));
+                            if let Some(sender) = exit_sender.take() {
+                                let _ = sender.send(status);
+                            }
+                        }
+                    }
+                }
+            }
+            record(&|recording| recording.finish(&ExitStatus::Unknown));
+            if let Some(sender) = exit_sender.filter(|_| allow_missing_exit_status) {
+                let _ = sender.send(ExitStatus::Unknown);
+            }
+        });
+
+        let stdin = match stdin {
+            Some(data) => {
+                if let Some(recording) = &recording {
+                    recording.lock().unwrap().input(data);
+                }
+                channel.data(data).await?;
+                channel.eof().await?;
+                None
+            }
+            None => Some(ChildStdin {
+                writer: Box::pin(channel.make_writer()),
+                channel: channel.clone(),
+                closed: false,
+                recording: recording.clone(),
+            }),
+        };
+        Ok(Self {
+            channel,
+            stdin,
+            stdout: Some(ChildOutput::new(stdout_receiver)),
+            stderr: Some(ChildOutput::new(stderr_receiver)),
+            exit: CommandExit::new(exit_receiver),
+            exit_status: None,
+            recording,
+        })
+    }
+
+    /// Take the stdin of the process, `None` if it was already taken or the
+    /// command had [`stdin`](crate::RemoteCommand::stdin) data.
+    pub fn stdin(&mut self) -> Option<ChildStdin> {
+        self.stdin.take()
+    }
+
+    /// Take the stdout of the process, `None` if it was already taken.
+    pub fn stdout(&mut self) -> Option<ChildOutput> {
+        self.stdout.take()
+    }
+
+    /// Take the stderr of the process, `None` if it was already taken.
+    pub fn stderr(&mut self) -> Option<ChildOutput> {
+        self.stderr.take()
+    }
+
+    /// Wait for the process to exit and return how it ended.
+    ///
+    /// Closes stdin first if it wasn't [taken](RemoteChild::stdin), so the
+    /// process doesn't wait for more input, and discards the output of the
+    /// handles which weren't taken. A taken [`ChildStdin`] stays open until it
+    /// is dropped or shut down, so do that before waiting for a process reading
+    /// all its input, like `cat`. Returns
+    /// [`Error::CommandDidntExit`](crate::Error::CommandDidntExit) if the channel
+    /// closed without an exit status, e.g. because it was closed by
+    /// [`kill`](RemoteChild::kill) on a server without signals, unless
+    /// [allowed](crate::Client::set_allow_missing_exit_status).
+    ///
+    /// For a [recorded](crate::Client::spawn_recorded) process, the recording is
+    /// finished then, and
+    /// [`Error::RecordingFailed`](crate::Error::RecordingFailed) returned if the
+    /// recorder failed on the way.
+    pub async fn wait(&mut self) -> Result<ExitStatus, crate::Error> {
+        self.stdin = None;
+        self.stdout = None;
+        self.stderr = None;
+        if let Some(exit_status) = &self.exit_status {
+            return Ok(exit_status.clone());
+        }
+        let exit_status = (&mut self.exit).await?;
+        self.exit_status = Some(exit_status.clone());
+        if let Some(error) = self
+            .recording
+            .as_ref()
+            .and_then(|recording| recording.lock().unwrap().take_error())
+        {
+            return Err(error);
+        }
+        Ok(exit_status)
+    }
+
+    /// Send `signal` to the process, e.g. [`Signal::TERM`] to let it shut down
+    /// gracefully before resorting to [`kill`](RemoteChild::kill).
+    ///
+    /// Servers silently ignore signals they don't support, OpenSSH delivers them
+    /// since 7.9.
+    pub async fn signal(&self, signal: Signal) -> Result<(), crate::Error> {
+        self.channel.signal(signal.into()).await?;
+        Ok(())
+    }
+
+    /// Change the size of the pseudo terminal the process runs in, e.g. when the
+    /// local terminal was resized, see [`PtyResizer::resize`].
+    pub async fn resize(&self, cols: u32, rows: u32) -> Result<(), crate::Error> {
+        self.resizer().resize(cols, rows).await
+    }
+
+    /// A handle changing the size of the pseudo terminal, to pass to the task
+    /// watching the local terminal while the process is waited for.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async fn example(
+    /// #     mut child: async_ssh2_tokio::RemoteChild,
+    /// #     mut local_sizes: tokio::sync::mpsc::Receiver<(u32, u32)>,
+    /// # ) -> Result<(), async_ssh2_tokio::Error> {
+    /// // Sizes of the local terminal, e.g. read on each `SIGWINCH`.
+    /// let resizer = child.resizer();
+    /// tokio::spawn(async move {
+    ///     while let Some((cols, rows)) = local_sizes.recv().await {
+    ///         let _ = resizer.resize(cols, rows).await;
+    ///     }
+    /// });
+    /// child.wait().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn resizer(&self) -> PtyResizer {
+        PtyResizer::new(self.channel.clone(), self.recording.clone())
+    }
+
+    /// Kill the process and close its channel.
+    ///
+    /// The process gets `SIGKILL`, if the server supports signals, and the hangup
+    /// from closing the channel otherwise.
+    pub async fn kill(&mut self) -> Result<(), crate::Error> {
+        self.stdin = None;
+        // Signals may be unsupported, so the close has to follow anyway.
+        let _ = self.signal(Signal::KILL).await;
+        self.channel.close().await?;
+        Ok(())
+    }
+}
+
+/// A signal for a remote process, named like in RFC 4254 section 6.10.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Signal {
+    ABRT,
+    ALRM,
+    FPE,
+    HUP,
+    ILL,
+    INT,
+    KILL,
+    PIPE,
+    QUIT,
+    SEGV,
+    TERM,
+    USR1,
+    Custom(String), // another signal by its name without `SIG`, e.g. `USR2`
+}
+
+impl Signal {
+    /// The name of the signal without `SIG`, as sent to the server.
+    pub fn name(&self) -> &str {
+        match self {
+            Signal::ABRT => "ABRT",
+            Signal::ALRM => "ALRM",
+            Signal::FPE => "FPE",
+            Signal::HUP => "HUP",
+            Signal::ILL => "ILL",
+            Signal::INT => "INT",
+            Signal::KILL => "KILL",
+            Signal::PIPE => "PIPE",
+            Signal::QUIT => "QUIT",
+            Signal::SEGV => "SEGV",
+            Signal::TERM => "TERM",
+            Signal::USR1 => "USR1",
+            Signal::Custom(name) => name,
+        }
+    }
+}
+
+impl Signal {
+    /// The number of the signal on Linux.
+    fn number(&self) -> Option<u32> {
+        match self {
+            Signal::HUP => Some(1),
+            Signal::INT => Some(2),
+            Signal::QUIT => Some(3),
+            Signal::ILL => Some(4),
+            Signal::ABRT => Some(6),
+            Signal::FPE => Some(8),
+            Signal::KILL => Some(9),
+            Signal::USR1 => Some(10),
+            Signal::SEGV => Some(11),
+            Signal::PIPE => Some(13),
+            Signal::ALRM => Some(14),
+            Signal::TERM => Some(15),
+            Signal::Custom(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SIG{}", self.name())
+    }
+}
+
+impl From<Signal> for Sig {
+    fn from(signal: Signal) -> Self {
+        match signal {
+            Signal::ABRT => Sig::ABRT,
+            Signal::ALRM => Sig::ALRM,
+            Signal::FPE => Sig::FPE,
+            Signal::HUP => Sig::HUP,
+            Signal::ILL => Sig::ILL,
+            Signal::INT => Sig::INT,
+            Signal::KILL => Sig::KILL,
+            Signal::PIPE => Sig::PIPE,
+            Signal::QUIT => Sig::QUIT,
+            Signal::SEGV => Sig::SEGV,
+            Signal::TERM => Sig::TERM,
+            Signal::USR1 => Sig::USR1,
+            Signal::Custom(name) => Sig::Custom(name),
+        }
+    }
+}
+
+impl From<Sig> for Signal {
+    fn from(signal: Sig) -> Self {
+        match signal {
+            Sig::ABRT => Signal::ABRT,
+            Sig::ALRM => Signal::ALRM,
+            Sig::FPE => Signal::FPE,
+            Sig::HUP => Signal::HUP,
+            Sig::ILL => Signal::ILL,
+            Sig::INT => Signal::INT,
+            Sig::KILL => Signal::KILL,
+            Sig::PIPE => Signal::PIPE,
+            Sig::QUIT => Signal::QUIT,
+            Sig::SEGV => Signal::SEGV,
+            Sig::TERM => Signal::TERM,
+            Sig::USR1 => Signal::USR1,
+            Sig::Custom(name) => Signal::Custom(name),
+        }
+    }
+}
+
+/// How a remote command ended.
+///
+/// The server reports either the exit code of the command, or the signal which
+/// terminated it (RFC 4254 section 6.10). Some servers like Dropbear on
+/// embedded devices may report neither, see
+/// [`Client::set_allow_missing_exit_status`](crate::Client::set_allow_missing_exit_status).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ExitStatus {
+    Exited(u32), // the command exited with this code
+    Signaled {
+        signal: Signal,    // the signal which terminated the command
+        core_dumped: bool, // whether a core dump was written
+        message: String,   // an error message by the server, often empty
+    },
+    Unknown, // the channel closed without exit status, counts as failure
+}
+
+impl ExitStatus {
+    /// Whether the command exited with code 0.
+    pub fn success(&self) -> bool {
+        self.code() == Some(0)
+    }
+
+    /// The exit code, `None` if the command was terminated by a signal.
+    pub fn code(&self) -> Option<u32> {
+        match self {
+            ExitStatus::Exited(code) => Some(*code),
+            ExitStatus::Signaled { .. } | ExitStatus::Unknown => None,
+        }
+    }
+
+    /// The signal which terminated the command.
+    pub fn signal(&self) -> Option<&Signal> {
+        match self {
+            ExitStatus::Exited(_) | ExitStatus::Unknown => None,
+            ExitStatus::Signaled { signal, .. } => Some(signal),
+        }
+    }
+
+    /// The status like `$?` in bash: the exit code, or 128 plus the signal
+    /// number on Linux. 255 for signals without a well-known number and unknown
+    /// statuses, like `ssh` exits with.
+    pub(crate) fn shell_status(&self) -> u32 {
+        match self {
+            ExitStatus::Exited(code) => *code,
+            ExitStatus::Signaled { signal, .. } => signal.number().map_or(255, |n| 128 + n),
+            ExitStatus::Unknown => 255,
+        }
+    }
+
+    /// The exit status reported by `msg`, if it is one.
+    pub(crate) fn from_msg(msg: &russh::ChannelMsg) -> Option<Self> {
+        match msg {
+            russh::ChannelMsg::ExitStatus { exit_status } => Some(ExitStatus::Exited(*exit_status)),
+            russh::ChannelMsg::ExitSignal {
+                signal_name,
+                core_dumped,
+                error_message,
+                ..
+            } => Some(ExitStatus::Signaled {
+                signal: signal_name.clone().into(),
+                core_dumped: *core_dumped,
+                message: error_message.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExitStatus::Exited(code) => write!(f, "exit code {code}"),
+            ExitStatus::Signaled {
+                signal,
+                core_dumped,
+                ..
+            } => {
+                write!(f, "terminated by {signal}")?;
+                if *core_dumped {
+                    write!(f, " (core dumped)")?;
+                }
+                Ok(())
+            }
+            ExitStatus::Unknown => write!(f, "unknown exit status"),
+        }
+    }
+}
+
+impl fmt::Debug for RemoteChild {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteChild")
+            .field("channel", &self.channel.id())
+            .field("exit_status", &self.exit_status)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The stdin of a [`RemoteChild`].
+///
+/// EOF is sent on [`shutdown`](tokio::io::AsyncWriteExt::shutdown), or when the
+/// handle is dropped.
+pub struct ChildStdin {
+    writer: Pin<Box<dyn AsyncWrite + Send>>,
+    channel: Arc<ChannelWriteHalf<Msg>>,
+    closed: bool,
+    recording: Option<Arc<Mutex<Recording>>>,
+}
+
+impl AsyncWrite for ChildStdin {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let written = ready!(self.writer.as_mut().poll_write(cx, buf));
+        if let (Ok(written), Some(recording)) = (&written, &self.recording) {
+            recording.lock().unwrap().input(&buf[..*written]);
+        }
+        Poll::Ready(written)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.writer.as_mut().poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let result = ready!(self.writer.as_mut().poll_shutdown(cx));
+        self.closed = true;
+        Poll::Ready(result)
+    }
+}
+
+impl Drop for ChildStdin {
+    fn drop(&mut self) {
+        if self.closed {
+            return;
+        }
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let channel = self.channel.clone();
+            runtime.spawn(async move {
+                let _ = channel.eof().await;
+            });
+        }
+    }
+}
+
+impl fmt::Debug for ChildStdin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChildStdin")
+            .field("channel", &self.channel.id())
+            .field("closed", &self.closed)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The stdout or stderr of a [`RemoteChild`], ends once the channel closes.
+///
+/// The output is read only as fast as it is consumed, so read stdout and stderr
+/// concurrently or drop the one you aren't interested in.
+#[derive(Debug)]
+pub struct ChildOutput {
+    receiver: mpsc::Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    offset: usize,
+}
+
+impl ChildOutput {
+    fn new(receiver: mpsc::Receiver<Vec<u8>>) -> Self {
+        Self {
+            receiver,
+            chunk: vec![],
+            offset: 0,
+        }
+    }
+}
+
+impl AsyncRead for ChildOutput {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        while this.offset == this.chunk.len() {
+            match ready!(this.receiver.poll_recv(cx)) {
+                Some(chunk) => {
+                    this.chunk = chunk;
+                    this.offset = 0;
+                }
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+        let len = buf.remaining().min(this.chunk.len() - this.offset);
+        buf.put_slice(&this.chunk[this.offset..this.offset + len]);
+        this.offset += len;
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[test]
+    fn signals() {
+        assert_eq!("TERM", Signal::TERM.name());
+        assert_eq!("SIGUSR2", Signal::Custom("USR2".to_string()).to_string());
+        assert_eq!(Signal::INT, Signal::from(Sig::from(Signal::INT)));
+        assert!(
+            matches!(Sig::from(Signal::Custom("USR2".to_string())), Sig::Custom(name) if name == "USR2")
+        );
+    }
+
+    #[test]
+    fn exit_status() {
+        let exited =
+            ExitStatus::from_msg(&russh::ChannelMsg::ExitStatus { exit_status: 3 }).unwrap();
+        assert_eq!(ExitStatus::Exited(3), exited);
+        assert_eq!(Some(3), exited.code());
+        assert_eq!(None, exited.signal());
+        assert!(!exited.success());
+        assert!(ExitStatus::Exited(0).success());
+        assert_eq!(3, exited.shell_status());
+        assert_eq!("exit code 3", exited.to_string());
+
+        let signaled = ExitStatus::from_msg(&russh::ChannelMsg::ExitSignal {
+            signal_name: Sig::SEGV,
+            core_dumped: true,
+            error_message: String::new(),
+            lang_tag: String::new(),
+        })
+        .unwrap();
+        assert_eq!(None, signaled.code());
+        assert_eq!(Some(&Signal::SEGV), signaled.signal());
+        assert!(!signaled.success());
+        assert_eq!(139, signaled.shell_status());
+        assert_eq!("terminated by SIGSEGV (core dumped)", signaled.to_string());
+
+        let custom = ExitStatus::Signaled {
+            signal: Signal::Custom("USR2".to_string()),
+            core_dumped: false,
+            message: String::new(),
+        };
+        assert_eq!(255, custom.shell_status());
+        assert!(!ExitStatus::Unknown.success());
+        assert_eq!(None, ExitStatus::Unknown.code());
+        assert_eq!(255, ExitStatus::Unknown.shell_status());
+        assert_eq!("unknown exit status", ExitStatus::Unknown.to_string());
+        assert!(ExitStatus::from_msg(&russh::ChannelMsg::Eof).is_none());
+    }
+
+    #[tokio::test]
+    async fn child_output() {
+        let (sender, receiver) = mpsc::channel(CHILD_OUTPUT_BUFFER);
+        let mut output = ChildOutput::new(receiver);
+        sender.send(b"foo".to_vec()).await.unwrap();
+        sender.send(vec![]).await.unwrap();
+        sender.send(b"bar baz".to_vec()).await.unwrap();
+        drop(sender);
+
+        let mut buf = [0; 5];
+        assert_eq!(3, output.read(&mut buf).await.unwrap());
+        assert_eq!(b"foo", &buf[..3]);
+        assert_eq!(5, output.read(&mut buf).await.unwrap());
+        assert_eq!(b"bar b", &buf);
+        let mut rest = String::new();
+        output.read_to_string(&mut rest).await.unwrap();
+        assert_eq!("az", rest);
+        assert_eq!(0, output.read(&mut buf).await.unwrap());
+    }
+}
@@ -0,0 +1,534 @@
+//! Build remote commands from a program and its arguments, without quoting them
+//! by hand.
+//!
+//! ```
+//! use async_ssh2_tokio::RemoteCommand;
+//!
+//! let command = RemoteCommand::new("grep")
+//!     .arg("-r")
+//!     .arg("it's here")
+//!     .arg("/var/log/my app")
+//!     .env("LC_ALL", "C");
+//! assert_eq!(command.to_string(), r"LC_ALL=C grep -r 'it'\''s here' '/var/log/my app'");
+//! ```
+
+use crate::pty::PtyRequest;
+use crate::quote;
+use std::borrow::Cow;
+use std::fmt;
+
+/// A command to run with [`Client::execute_command`], modeled on
+/// [`std::process::Command`].
+///
+/// The program, arguments and environment are quoted for the [`Shell`] of the
+/// client, a POSIX shell by default. So they are passed literally, including
+/// spaces, quotes, `$` and alike.
+///
+/// [`Client::execute_command`]: crate::Client::execute_command
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RemoteCommand {
+    program: String,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    current_dir: Option<String>,
+    stdin: Option<Vec<u8>>,
+    pty: Option<PtyRequest>,
+}
+
+impl RemoteCommand {
+    /// A command running `program`, looked up in the `PATH` of the remote user.
+    pub fn new(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+            args: vec![],
+            env: vec![],
+            current_dir: None,
+            stdin: None,
+            pty: None,
+        }
+    }
+
+    /// Add an argument.
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Add several arguments.
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Set an environment variable for the program.
+    ///
+    /// Names must consist of ASCII letters, digits and `_`, and must not start
+    /// with a digit, otherwise the command fails with
+    /// [`Error::EnvironmentVariableInvalid`](crate::Error::EnvironmentVariableInvalid).
+    /// With [`Shell::Cmd`], values can't contain `"`, carriage returns or
+    /// newlines either, and variables like `%PATH%` in them are expanded.
+    pub fn env(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set several environment variables for the program.
+    pub fn envs<I, K, V>(mut self, env: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.env.extend(
+            env.into_iter()
+                .map(|(name, value)| (name.into(), value.into())),
+        );
+        self
+    }
+
+    /// Run the program in `dir` instead of the home directory.
+    pub fn current_dir(mut self, dir: impl Into<String>) -> Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    /// Send `data` to the stdin of the program. EOF follows it.
+    pub fn stdin(mut self, data: impl Into<Vec<u8>>) -> Self {
+        self.stdin = Some(data.into());
+        self
+    }
+
+    /// Run the program in a pseudo terminal, for programs which refuse to run
+    /// or behave differently without one. Its stderr is merged into stdout.
+    pub fn pty(mut self, pty: PtyRequest) -> Self {
+        self.pty = Some(pty);
+        self
+    }
+
+    /// The program to run.
+    pub fn get_program(&self) -> &str {
+        &self.program
+    }
+
+    /// The arguments of the program.
+    pub fn get_args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// The environment variables set for the program.
+    pub fn get_envs(&self) -> &[(String, String)] {
+        &self.env
+    }
+
+    /// The directory the program runs in, if not the home directory.
+    pub fn get_current_dir(&self) -> Option<&str> {
+        self.current_dir.as_deref()
+    }
+
+    /// The data sent to the stdin of the program.
+    pub fn get_stdin(&self) -> Option<&[u8]> {
+        self.stdin.as_deref()
+    }
+
+    /// The pseudo terminal the program runs in.
+    pub fn get_pty(&self) -> Option<&PtyRequest> {
+        self.pty.as_ref()
+    }
+
+    /// The command line for `shell`, before it is wrapped by [`Shell::wrap`].
+    /// The same as the [`Display`](fmt::Display) output for POSIX shells.
+    pub fn command_line(&self, shell: Shell) -> String {
+        match shell {
+            Shell::PowerShell => self.powershell_command_line(),
+            Shell::Cmd => self.cmd_command_line(),
+            _ => self.to_string(),
+        }
+    }
+
+    fn powershell_command_line(&self) -> String {
+        let mut line = String::new();
+        if let Some(dir) = &self.current_dir {
+            line += &cd_prefix(Shell::PowerShell, dir);
+        }
+        for (name, value) in &self.env {
+            line += &format!("$env:{name} = {}; ", quote::powershell(value));
+        }
+        line += &quote::join_powershell(self.argv());
+        line
+    }
+
+    fn cmd_command_line(&self) -> String {
+        let mut line = String::new();
+        if let Some(dir) = &self.current_dir {
+            line += &cd_prefix(Shell::Cmd, dir);
+        }
+        for (name, value) in &self.env {
+            line += &cmd_set(name, value);
+        }
+        line += &quote::join_cmd(self.argv());
+        line
+    }
+
+    /// The program followed by its arguments.
+    fn argv(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.program.as_str()).chain(self.args.iter().map(String::as_str))
+    }
+
+    /// The name of the first environment variable which can't be set in `shell`.
+    pub(crate) fn invalid_env(&self, shell: Shell) -> Option<&str> {
+        self.env
+            .iter()
+            .find(|(name, value)| !env_valid(shell, name, value))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// The command line sent to the server.
+impl fmt::Display for RemoteCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(dir) = &self.current_dir {
+            f.write_str(&cd_prefix(Shell::Login, dir))?;
+        }
+        for (name, value) in &self.env {
+            write!(f, "{name}={} ", quote::posix(value))?;
+        }
+        f.write_str(&quote::join_posix(self.argv()))
+    }
+}
+
+/// How the server interprets command lines, see [`Client::set_shell`].
+///
+/// Servers pass command lines to the login shell of the user. With a
+/// different shell, the command line is wrapped to run in it instead, e.g. for
+/// users with `fish` or `csh` as login shell.
+///
+/// The commands of ssh sessions don't run in a login shell, so the profile
+/// isn't loaded, and with it neither the `PATH` set up by tools like rbenv, nvm
+/// or environment modules on HPC clusters. [`BashLogin`](Shell::BashLogin)
+/// runs commands with `bash -l`, which loads `/etc/profile` and the first of
+/// `~/.bash_profile`, `~/.bash_login` and `~/.profile`.
+///
+/// With [`Auto`](Shell::Auto), the [`LoginShell`] is detected before the first
+/// command, so POSIX login shells run commands directly and fish or csh ones
+/// are handed a command line quoted for them which runs `sh`. Commands run by
+/// [`Client::run_script`] and [`Client::execute_sudo`] need a POSIX shell.
+///
+/// Win32-OpenSSH runs commands in `cmd.exe` unless configured otherwise, where
+/// [`Cmd`](Shell::Cmd) quotes [`RemoteCommand`]s correctly. `%` can't be
+/// escaped for `cmd.exe`, so variables like `%PATH%` in arguments are expanded.
+/// [`PowerShell`](Shell::PowerShell) has no such gaps.
+///
+/// [`Client::set_shell`]: crate::Client::set_shell
+/// [`Client::login_shell`]: crate::Client::login_shell
+/// [`Client::run_script`]: crate::Client::run_script
+/// [`Client::execute_sudo`]: crate::Client::execute_sudo
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Shell {
+    Login,      // the login shell, assumed to be POSIX for quoting
+    Sh,         // `sh -c`, quoted for a POSIX login shell
+    Bash,       // `bash -c`, quoted for a POSIX login shell
+    BashLogin,  // `bash -lc`, loads the profile before the command
+    PowerShell, // `powershell -EncodedCommand`, works from any login shell
+    Cmd,        // `cmd.exe` as login shell, command lines are passed unchanged
+    Auto,       // `Login` or `Sh`, after detecting the login shell with `Client::login_shell`
+}
+
+impl Shell {
+    /// The command line running `command` in this shell.
+    ///
+    /// PowerShell commands are passed base64 encoded, so they need no quoting
+    /// for the login shell, which is `cmd.exe` for Win32-OpenSSH by default.
+    /// PowerShell only exits with 0 or 1 by itself. So the exit code of the last
+    /// program (`$LASTEXITCODE`) is passed on instead, or 1 if the last cmdlet
+    /// failed, like the `%ERRORLEVEL%` of `cmd.exe`.
+    ///
+    /// The command line is quoted for a POSIX login shell, [`Auto`](Shell::Auto)
+    /// is taken as [`Login`](Shell::Login) here.
+    pub fn wrap<'a>(&self, command: &'a str) -> Cow<'a, str> {
+        self.wrap_for(LoginShell::Posix, command)
+    }
+
+    /// [`wrap`](Shell::wrap) for the `login` shell.
+    pub(crate) fn wrap_for<'a>(&self, login: LoginShell, command: &'a str) -> Cow<'a, str> {
+        match self {
+            Self::Login | Self::Cmd | Self::Auto => Cow::Borrowed(command),
+            Self::Sh => Cow::Owned(format!("sh -c {}", login.quote(command))),
+            Self::Bash => Cow::Owned(format!("bash -c {}", login.quote(command))),
+            Self::BashLogin => Cow::Owned(format!("bash -lc {}", login.quote(command))),
+            Self::PowerShell => {
+                let script = format!(
+                    "$global:LASTEXITCODE = 0\n{command}\n$succeeded = $?\n\
+                     if ($LASTEXITCODE) {{ exit $LASTEXITCODE }} elseif (-not $succeeded) {{ exit 1 }}"
+                );
+                let utf16 = script
+                    .encode_utf16()
+                    .flat_map(u16::to_le_bytes)
+                    .collect::<Vec<_>>();
+                Cow::Owned(format!(
+                    "powershell -NoProfile -NonInteractive -EncodedCommand {}",
+                    data_encoding::BASE64.encode(&utf16)
+                ))
+            }
+        }
+    }
+}
+
+/// The kind of the login shell of the remote user, which interprets the command
+/// lines sent to the server, see [`Client::login_shell`].
+///
+/// [`Client::login_shell`]: crate::Client::login_shell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum LoginShell {
+    Posix,   // sh, bash, dash, BusyBox ash, ksh or zsh
+    Fish,    // fish, quoted with `quote::fish`
+    Csh,     // csh or tcsh, quoted with `quote::csh`
+    Unknown, // anything else, e.g. `cmd.exe` or PowerShell on Windows
+}
+
+impl LoginShell {
+    /// The kind of the shell at `path`, e.g. the `$SHELL` of the user.
+    pub(crate) fn from_path(path: &str) -> Self {
+        let name = path.trim().rsplit('/').next().unwrap_or_default();
+        match name.strip_prefix('-').unwrap_or(name) {
+            "sh" | "bash" | "dash" | "ash" | "busybox" | "ksh" | "mksh" | "pdksh" | "zsh"
+            | "yash" => Self::Posix,
+            "fish" => Self::Fish,
+            "csh" | "tcsh" => Self::Csh,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// The [`Shell`] commands run in for [`Shell::Auto`].
+    pub(crate) fn auto_shell(self) -> Shell {
+        match self {
+            Self::Fish | Self::Csh => Shell::Sh,
+            _ => Shell::Login,
+        }
+    }
+
+    /// Quote `word` for this shell, as for POSIX shells if unknown.
+    pub(crate) fn quote(self, word: &str) -> Cow<'_, str> {
+        match self {
+            Self::Fish => quote::fish(word),
+            Self::Csh => quote::csh(word),
+            _ => quote::posix(word),
+        }
+    }
+}
+
+/// Change into `dir` before the command following in `shell`, which doesn't run
+/// if that fails.
+pub(crate) fn cd_prefix(shell: Shell, dir: &str) -> String {
+    match shell {
+        Shell::PowerShell => format!(
+            "Set-Location -LiteralPath {} -ErrorAction Stop; ",
+            quote::powershell(dir)
+        ),
+        Shell::Cmd => format!("cd /d {} && ", quote::cmd(dir)),
+        _ => format!("cd {} && ", quote::posix(dir)),
+    }
+}
+
+/// Change into `dir` before the script following in `shell`, which stops if
+/// that fails. Unlike [`cd_prefix`], this covers scripts of several commands.
+pub(crate) fn cd_line(shell: Shell, dir: &str) -> String {
+    match shell {
+        // Fails with a terminating error.
+        Shell::PowerShell => cd_prefix(shell, dir),
+        Shell::Cmd => format!("cd /d {} || exit 1 & ", quote::cmd(dir)),
+        _ => format!("cd {} || exit\n", quote::posix(dir)),
+    }
+}
+
+/// The command line running `command` as `user` with non-interactive sudo, in
+/// `bash` for [`Shell::Bash`], a login `bash` for [`Shell::BashLogin`] and `sh`
+/// otherwise, quoted for the `login` shell.
+pub(crate) fn sudo_line(shell: Shell, login: LoginShell, user: &str, command: &str) -> String {
+    let shell = match shell {
+        Shell::Bash => "bash",
+        Shell::BashLogin => "bash -l",
+        _ => "sh",
+    };
+    format!(
+        "sudo -n -H -u {} -- {shell} -c {}",
+        login.quote(user),
+        login.quote(command)
+    )
+}
+
+/// `set "name=value" && `, setting a variable for the rest of a `cmd.exe`
+/// command line. Checked with [`env_valid`], as a `"` in `value` would end the
+/// quoting.
+pub(crate) fn cmd_set(name: &str, value: &str) -> String {
+    format!("set \"{name}={value}\" && ")
+}
+
+/// Whether `name` can be set to `value` as environment variable by `shell`.
+///
+/// `cmd.exe` can't escape quotes or line breaks in the quoted `set`, so they'd
+/// inject commands.
+pub(crate) fn env_valid(shell: Shell, name: &str, value: &str) -> bool {
+    env_name_valid(name) && (shell != Shell::Cmd || !value.contains(['"', '\r', '\n']))
+}
+
+/// Whether `name` can be set as environment variable by a shell.
+pub(crate) fn env_name_valid(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shells() {
+        assert_eq!("echo $HOME", Shell::Login.wrap("echo $HOME"));
+        assert_eq!("sh -c 'echo $HOME'", Shell::Sh.wrap("echo $HOME"));
+        assert_eq!(r"bash -c 'it'\''s'", Shell::Bash.wrap("it's"));
+        assert_eq!("bash -lc 'echo $PATH'", Shell::BashLogin.wrap("echo $PATH"));
+        assert_eq!("dir /b", Shell::Cmd.wrap("dir /b"));
+        assert_eq!("echo $HOME", Shell::Auto.wrap("echo $HOME"));
+        assert_eq!(
+            r"sh -c 'echo \'hi\''",
+            Shell::Sh.wrap_for(LoginShell::Fish, "echo 'hi'")
+        );
+        assert_eq!(
+            "sh -c 'echo a\\\nb'",
+            Shell::Sh.wrap_for(LoginShell::Csh, "echo a\nb")
+        );
+        let powershell = Shell::PowerShell.wrap("dir");
+        let encoded = powershell
+            .strip_prefix("powershell -NoProfile -NonInteractive -EncodedCommand ")
+            .unwrap();
+        let utf16 = data_encoding::BASE64.decode(encoded.as_bytes()).unwrap();
+        let utf16 = utf16
+            .chunks(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            "$global:LASTEXITCODE = 0\ndir\n$succeeded = $?\n\
+             if ($LASTEXITCODE) { exit $LASTEXITCODE } elseif (-not $succeeded) { exit 1 }",
+            String::from_utf16(&utf16).unwrap()
+        );
+
+        let command = RemoteCommand::new(r"C:\Program Files\app.exe")
+            .arg("it's")
+            .env("MODE", "fast")
+            .current_dir(r"C:\Temp");
+        assert_eq!(
+            r"Set-Location -LiteralPath 'C:\Temp' -ErrorAction Stop; $env:MODE = 'fast'; & 'C:\Program Files\app.exe' 'it''s'",
+            command.command_line(Shell::PowerShell)
+        );
+        assert_eq!(
+            r#"cd /d C:\Temp && set "MODE=fast" && "C:\Program Files\app.exe" "it's""#,
+            command.command_line(Shell::Cmd)
+        );
+        assert_eq!(command.to_string(), command.command_line(Shell::Bash));
+    }
+
+    #[test]
+    fn sudo_lines() {
+        assert_eq!(
+            r"sudo -n -H -u postgres -- sh -c 'psql -c '\''\l'\'''",
+            sudo_line(Shell::Login, LoginShell::Posix, "postgres", r"psql -c '\l'")
+        );
+        assert_eq!(
+            "sudo -n -H -u 'www data' -- bash -c 'echo $HOME'",
+            sudo_line(Shell::Bash, LoginShell::Posix, "www data", "echo $HOME")
+        );
+        assert_eq!(
+            "sudo -n -H -u deploy -- bash -l -c 'rbenv version'",
+            sudo_line(
+                Shell::BashLogin,
+                LoginShell::Posix,
+                "deploy",
+                "rbenv version"
+            )
+        );
+        assert_eq!(
+            r"sudo -n -H -u root -- sh -c 'echo \'hi\''",
+            sudo_line(Shell::Auto, LoginShell::Fish, "root", "echo 'hi'")
+        );
+    }
+
+    #[test]
+    fn cd_lines() {
+        assert_eq!("cd /tmp || exit\n", cd_line(Shell::Login, "/tmp"));
+        assert_eq!("cd 'my dir' || exit\n", cd_line(Shell::Bash, "my dir"));
+        assert_eq!(
+            r#"cd /d "C:\My Dir" || exit 1 & "#,
+            cd_line(Shell::Cmd, r"C:\My Dir")
+        );
+        assert_eq!(
+            "Set-Location -LiteralPath 'C:\\' -ErrorAction Stop; ",
+            cd_line(Shell::PowerShell, "C:\\")
+        );
+    }
+
+    #[test]
+    fn login_shells() {
+        assert_eq!(LoginShell::Posix, LoginShell::from_path("/bin/bash\n"));
+        assert_eq!(LoginShell::Posix, LoginShell::from_path("/bin/ash"));
+        assert_eq!(LoginShell::Posix, LoginShell::from_path("-zsh"));
+        assert_eq!(
+            LoginShell::Fish,
+            LoginShell::from_path("/usr/local/bin/fish")
+        );
+        assert_eq!(LoginShell::Csh, LoginShell::from_path("/bin/tcsh"));
+        assert_eq!(LoginShell::Unknown, LoginShell::from_path("$SHELL"));
+        assert_eq!(LoginShell::Unknown, LoginShell::from_path(""));
+        assert_eq!(Shell::Sh, LoginShell::Fish.auto_shell());
+        assert_eq!(Shell::Login, LoginShell::Unknown.auto_shell());
+    }
+
+    #[test]
+    fn env_names() {
+        assert!(env_name_valid("FOO"));
+        assert!(env_name_valid("_foo_1"));
+        assert!(!env_name_valid(""));
+        assert!(!env_name_valid("1FOO"));
+        assert!(!env_name_valid("FOO BAR"));
+        assert!(!env_name_valid("FOO=BAR"));
+    }
+
+    #[test]
+    fn command_line() {
+        assert_eq!("ls", RemoteCommand::new("ls").to_string());
+        assert_eq!(
+            "'./a=b' c=d",
+            RemoteCommand::new("./a=b").arg("c=d").to_string()
+        );
+        assert_eq!(
+            "cd '/srv/my app' && RAILS_ENV=production FOO='a b' bin/rails runner 'puts 1'",
+            RemoteCommand::new("bin/rails")
+                .args(["runner", "puts 1"])
+                .envs([("RAILS_ENV", "production"), ("FOO", "a b")])
+                .current_dir("/srv/my app")
+                .to_string()
+        );
+
+        let command = RemoteCommand::new("cat").stdin("foo").env("1FOO", "bar");
+        assert_eq!(Some(&b"foo"[..]), command.get_stdin());
+        assert_eq!(Some("1FOO"), command.invalid_env(Shell::Login));
+        assert_eq!(None, RemoteCommand::new("cat").invalid_env(Shell::Login));
+
+        let command = RemoteCommand::new("echo").env("FOO", r#"" & del /q *"#);
+        assert_eq!(None, command.invalid_env(Shell::Login));
+        assert_eq!(None, command.invalid_env(Shell::PowerShell));
+        assert_eq!(Some("FOO"), command.invalid_env(Shell::Cmd));
+        let command = RemoteCommand::new("echo").env("FOO", "a\r\ndel /q *");
+        assert_eq!(Some("FOO"), command.invalid_env(Shell::Cmd));
+        let command = RemoteCommand::new("echo").env("FOO", "100% & more");
+        assert_eq!(None, command.invalid_env(Shell::Cmd));
+    }
+}
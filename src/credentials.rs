@@ -0,0 +1,78 @@
+//! Authentification with secrets fetched on demand from a [`CredentialProvider`],
+//! e.g. Vault, AWS Secrets Manager or the keychain of the OS.
+//!
+//! The provider is only asked for what the authentification needs: a private
+//! key first, its passphrase only if the key is encrypted, and a password if
+//! there is no key or the server rejects it.
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// The future returned by the methods of a [`CredentialProvider`].
+pub type CredentialFuture<'a> = Pin<Box<dyn Future<Output = Option<String>> + Send + 'a>>;
+
+/// Which connection a credential is requested for.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct CredentialRequest {
+    /// The address the client connected to.
+    pub address: SocketAddr,
+    /// The user to authenticate as.
+    pub username: String,
+}
+
+/// A source of secrets for [`AuthMethod::with_credential_provider`].
+///
+/// Every method defaults to returning `None`, meaning the provider has no such
+/// credential, so implementations only override what they can supply.
+///
+/// ```
+/// use async_ssh2_tokio::credentials::{CredentialFuture, CredentialProvider, CredentialRequest};
+///
+/// struct Environment;
+///
+/// impl CredentialProvider for Environment {
+///     fn password<'a>(&'a self, request: &'a CredentialRequest) -> CredentialFuture<'a> {
+///         Box::pin(async move { std::env::var(format!("SSH_PASSWORD_{}", request.username)).ok() })
+///     }
+/// }
+/// ```
+///
+/// [`AuthMethod::with_credential_provider`]: crate::AuthMethod::with_credential_provider
+pub trait CredentialProvider: Send + Sync {
+    /// The entire contents of a private key file, in any format accepted by
+    /// [`AuthMethod::with_key`](crate::AuthMethod::with_key).
+    fn private_key<'a>(&'a self, _request: &'a CredentialRequest) -> CredentialFuture<'a> {
+        Box::pin(async { None })
+    }
+
+    /// The passphrase of the key returned by [`private_key`](Self::private_key),
+    /// only requested if the key is encrypted.
+    fn key_passphrase<'a>(&'a self, _request: &'a CredentialRequest) -> CredentialFuture<'a> {
+        Box::pin(async { None })
+    }
+
+    /// The password of the user.
+    fn password<'a>(&'a self, _request: &'a CredentialRequest) -> CredentialFuture<'a> {
+        Box::pin(async { None })
+    }
+}
+
+/// A shared [`CredentialProvider`], as held by [`AuthMethod::CredentialProvider`].
+///
+/// Handles compare equal when they hold the same provider instance, i.e. when
+/// one is a clone of the other.
+///
+/// [`AuthMethod::CredentialProvider`]: crate::AuthMethod::CredentialProvider
+#[derive(Clone)]
+pub struct CredentialProviderHandle(pub(crate) Arc<dyn CredentialProvider>);
+
+impl CredentialProviderHandle {
+    pub fn new(provider: impl CredentialProvider + 'static) -> Self {
+        Self(Arc::new(provider))
+    }
+}
+
+handler_identity!(CredentialProviderHandle);
@@ -11,14 +11,90 @@ pub enum Error {
     KeyAuthFailed,
     #[error("Unable to load key, bad format or passphrase")]
     KeyInvalid,
+    #[error("Unable to load certificate, bad format")]
+    CertificateInvalid,
+    #[error("Security key didn't provide a signature")]
+    SecurityKeyFailed,
     #[error("Password authentification failed")]
     PasswordWrong,
+    #[error("Unable to connect to the ssh-agent")]
+    AgentConnectionFailed,
+    #[error("Unable to request the identities of the ssh-agent")]
+    AgentRequestIdentitiesFailed,
+    #[error("Agent authentification failed, no identity was accepted")]
+    AgentAuthFailed,
+    #[error("Keyboard-interactive authentification failed")]
+    KeyboardInteractiveAuthFailed,
+    #[error("The server requires a password change: {0}")]
+    PasswordChangeRequired(String),
+    #[error("Invalid prompt pattern: {0}")]
+    PromptPatternInvalid(String),
+    #[cfg(feature = "gssapi")]
+    #[error("GSSAPI authentification failed: {0}")]
+    GssapiFailed(String),
+    #[cfg(feature = "pkcs11")]
+    #[error("PKCS#11 token failed: {0}")]
+    Pkcs11Failed(String),
+    #[error("Authentification partially succeeded, the server requires a further method")]
+    PartialSuccess,
+    #[error("No authentification method was provided")]
+    NoAuthMethod,
     #[error("Invalid address was provided")]
     AddressInvalid(io::Error),
     #[error("The executed command didn't send an exit code")]
     CommandDidntExit,
+    #[error("The executed command timed out")]
+    Timeout { stdout: String, stderr: String },
+    #[error("The executed command was cancelled")]
+    Cancelled { stdout: String, stderr: String },
+    #[error("The executed command produced no output for {timeout:?}")]
+    Stalled {
+        stdout: String,
+        stderr: String,
+        timeout: std::time::Duration,
+    },
+    #[error("The host didn't come back within {0:?} after the reboot")]
+    RebootTimedOut(std::time::Duration),
+    #[error("The shell session ended")]
+    ShellSessionEnded,
+    #[error("The server refused to allocate a pseudo terminal")]
+    PtyRequestRejected,
+    #[error("Unable to use the local terminal")]
+    TerminalFailed(io::Error),
+    #[error("Unable to record the session")]
+    RecordingFailed(io::Error),
+    #[error("The prompt didn't appear within the timeout")]
+    PromptTimedOut { output: String },
+    #[error("Invalid environment variable name or value: {0}")]
+    EnvironmentVariableInvalid(String),
+    #[error("Unable to write the command output")]
+    OutputWriteFailed(io::Error),
+    #[error("The command output exceeded the limit of {0} bytes")]
+    OutputLimitExceeded(usize),
+    #[error("The command output isn't valid in the chosen encoding")]
+    OutputDecodingFailed { stdout: Vec<u8>, stderr: Vec<u8> },
+    #[error("The command failed with exit status {}", .0.exit_status)]
+    CommandFailed(Box<crate::client::CommandExecutedResult>),
+    #[error("Unable to parse the command output")]
+    OutputParseFailed(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("sudo rejected the password")]
+    SudoPasswordRejected,
     #[error("Server check failed")]
     ServerCheckFailed,
+    #[error("The server key is revoked")]
+    ServerKeyRevoked,
+    #[error("The server key {presented} doesn't match the expected {expected}")]
+    ServerKeyMismatch { expected: String, presented: String },
+    #[error("The host {host} is unknown, its key is {presented}")]
+    ServerKeyUnknown { host: String, presented: String },
+    #[error("Unable to use the host key file {path}: {reason}")]
+    HostKeyFileFailed { path: String, reason: String }, // known_hosts, public or revoked key file
+    #[error("Invalid host key algorithms: {0}")]
+    HostKeyAlgorithmsInvalid(String),
+    #[error("The server didn't send a valid identification string")]
+    ServerVersionInvalid,
+    #[error("Invalid known_hosts entry: {0}")]
+    KnownHostEntryInvalid(String),
     #[error("Ssh error occured")]
     SshError(#[from] russh::Error),
 }
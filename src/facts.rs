@@ -0,0 +1,283 @@
+//! Facts about a remote host, like its operating system, distribution and init
+//! system, to decide which commands to run on it.
+//!
+//! ```no_run
+//! use async_ssh2_tokio::{AuthMethod, Client, Facts, InitSystem, ServerCheckMethod};
+//! #[tokio::main]
+//! async fn main() -> Result<(), async_ssh2_tokio::Error> {
+//!     let client = Client::connect(
+//!         ("10.10.10.2", 22),
+//!         "root",
+//!         AuthMethod::with_password("root"),
+//!         ServerCheckMethod::NoCheck,
+//!     ).await?;
+//!
+//!     let facts = Facts::gather(&client).await?;
+//!     let install = match facts.distro.as_deref() {
+//!         Some("debian" | "ubuntu") => "apt-get install -y nginx",
+//!         Some("alpine") => "apk add nginx",
+//!         _ if facts.is_like("rhel") => "dnf install -y nginx",
+//!         other => panic!("unsupported distribution {other:?}"),
+//!     };
+//!     client.execute_checked(install).await?;
+//!     if facts.init_system == InitSystem::Systemd {
+//!         client.execute_checked("systemctl enable --now nginx").await?;
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use crate::Client;
+use std::collections::HashMap;
+
+/// Prints `key=value` lines, the lowercase keys first, then os-release.
+const PROBE: &str = r#"echo "kernel_name=$(uname -s)"
+echo "kernel_release=$(uname -r)"
+echo "machine=$(uname -m)"
+echo "hostname=$(uname -n)"
+if [ -d /run/systemd/system ]; then init=systemd
+elif [ -x /sbin/launchd ]; then init=launchd
+elif [ -x /sbin/openrc ] || [ -d /run/openrc ]; then init=openrc
+elif [ -d /run/runit ] || [ -d /etc/runit/runsvdir ]; then init=runit
+elif /sbin/initctl version 2>/dev/null | grep -q upstart; then init=upstart
+else init=$(cat /proc/1/comm 2>/dev/null)
+fi
+echo "init=$init"
+echo "macos_version=$(sw_vers -productVersion 2>/dev/null)"
+cat /etc/os-release 2>/dev/null || cat /usr/lib/os-release 2>/dev/null
+true"#;
+
+/// What a remote host runs, see [`Facts::gather`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Facts {
+    /// The family of the operating system.
+    pub os_family: OsFamily,
+    /// The id of the distribution, `ID` of os-release like `"ubuntu"` or
+    /// `"alpine"`, `"macos"` on macOS.
+    pub distro: Option<String>,
+    /// The ids of the distributions this one derives from, `ID_LIKE` of
+    /// os-release like `["rhel", "fedora"]` for Rocky Linux.
+    pub distro_like: Vec<String>,
+    /// The version of the distribution, `VERSION_ID` of os-release like
+    /// `"24.04"`.
+    pub distro_version: Option<String>,
+    /// The name of the distribution for humans, `PRETTY_NAME` of os-release like
+    /// `"Ubuntu 24.04.1 LTS"`.
+    pub distro_name: Option<String>,
+    /// The release of the kernel, `uname -r` like `"6.8.0-45-generic"`.
+    pub kernel: String,
+    /// The hardware architecture, `uname -m` like `"x86_64"` or `"aarch64"`.
+    pub architecture: String,
+    /// The name of the host, `uname -n`.
+    pub hostname: String,
+    /// The init system managing the services.
+    pub init_system: InitSystem,
+}
+
+impl Facts {
+    /// Collect the facts about the host `client` is connected to.
+    ///
+    /// Runs a single command which needs a POSIX shell and `uname`, so it
+    /// doesn't work on Windows. The distribution is read from
+    /// `/etc/os-release`, which all current Linux distributions have.
+    pub async fn gather(client: &Client) -> Result<Self, crate::Error> {
+        let result = client.execute_checked(PROBE).await?;
+        Ok(Self::parse(&result.stdout))
+    }
+
+    /// Whether the distribution is `id` or derives from it, e.g.
+    /// `facts.is_like("debian")` for Ubuntu.
+    pub fn is_like(&self, id: &str) -> bool {
+        self.distro.as_deref() == Some(id) || self.distro_like.iter().any(|like| like == id)
+    }
+
+    /// Parse the output of [`PROBE`].
+    fn parse(output: &str) -> Self {
+        let mut values = HashMap::new();
+        for line in output.lines() {
+            if let Some((key, value)) = line.split_once('=') {
+                values
+                    .entry(key.trim())
+                    .or_insert_with(|| unquote(value.trim()));
+            }
+        }
+        let mut value = |key| {
+            values
+                .remove(key)
+                .filter(|value: &String| !value.is_empty())
+        };
+
+        let os_family = OsFamily::from_kernel_name(&value("kernel_name").unwrap_or_default());
+        let macos_version = value("macos_version");
+        let (distro, distro_version) = match (value("ID"), macos_version) {
+            (None, Some(version)) if os_family == OsFamily::MacOs => {
+                (Some("macos".to_string()), Some(version))
+            }
+            (id, _) => (id, value("VERSION_ID")),
+        };
+        Self {
+            os_family,
+            distro,
+            distro_like: value("ID_LIKE")
+                .map(|like| like.split_whitespace().map(str::to_string).collect())
+                .unwrap_or_default(),
+            distro_version,
+            distro_name: value("PRETTY_NAME"),
+            kernel: value("kernel_release").unwrap_or_default(),
+            architecture: value("machine").unwrap_or_default(),
+            hostname: value("hostname").unwrap_or_default(),
+            init_system: InitSystem::from_name(&value("init").unwrap_or_default()),
+        }
+    }
+}
+
+/// The value of an os-release variable, which may be quoted like in a shell.
+fn unquote(value: &str) -> String {
+    let quoted = value.len() >= 2
+        && (value.starts_with('"') && value.ends_with('"')
+            || value.starts_with('\'') && value.ends_with('\''));
+    if !quoted {
+        return value.to_string();
+    }
+    let inner = &value[1..value.len() - 1];
+    if value.starts_with('\'') {
+        return inner.to_string();
+    }
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.extend(chars.next()),
+            c => unquoted.push(c),
+        }
+    }
+    unquoted
+}
+
+/// The family of the operating system of a remote host, see [`Facts`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum OsFamily {
+    Linux,         // any Linux distribution
+    MacOs,         // macOS, `Darwin` by `uname`
+    FreeBsd,       // FreeBSD
+    OpenBsd,       // OpenBSD
+    NetBsd,        // NetBSD
+    Solaris,       // Solaris and illumos, `SunOS` by `uname`
+    Other(String), // the kernel name printed by `uname -s`
+}
+
+impl OsFamily {
+    /// The family of the kernel named `name` by `uname -s`.
+    fn from_kernel_name(name: &str) -> Self {
+        match name {
+            "Linux" => Self::Linux,
+            "Darwin" => Self::MacOs,
+            "FreeBSD" => Self::FreeBsd,
+            "OpenBSD" => Self::OpenBsd,
+            "NetBSD" => Self::NetBsd,
+            "SunOS" => Self::Solaris,
+            name => Self::Other(name.to_string()),
+        }
+    }
+}
+
+/// The init system of a remote host, see [`Facts`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum InitSystem {
+    Systemd,       // services managed with `systemctl`
+    OpenRc,        // services managed with `rc-service`, e.g. on Alpine
+    Runit,         // services managed with `sv`, e.g. on Void Linux
+    Upstart,       // services managed with `initctl`, e.g. on old Ubuntu
+    Launchd,       // services managed with `launchctl` on macOS
+    Other(String), // the name of process 1 like `init`, empty if unknown
+}
+
+impl InitSystem {
+    /// The init system named `name` by the probe.
+    fn from_name(name: &str) -> Self {
+        match name {
+            "systemd" => Self::Systemd,
+            "openrc" | "openrc-init" => Self::OpenRc,
+            "runit" => Self::Runit,
+            "upstart" => Self::Upstart,
+            "launchd" => Self::Launchd,
+            name => Self::Other(name.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linux_facts() {
+        let facts = Facts::parse(
+            "kernel_name=Linux\n\
+             kernel_release=6.8.0-45-generic\n\
+             machine=x86_64\n\
+             hostname=web-1\n\
+             init=systemd\n\
+             macos_version=\n\
+             PRETTY_NAME=\"Rocky Linux 9.4 (Blue Onyx)\"\n\
+             NAME=\"Rocky Linux\"\n\
+             ID=\"rocky\"\n\
+             ID_LIKE=\"rhel centos fedora\"\n\
+             VERSION_ID=\"9.4\"\n",
+        );
+        assert_eq!(OsFamily::Linux, facts.os_family);
+        assert_eq!(Some("rocky"), facts.distro.as_deref());
+        assert_eq!(vec!["rhel", "centos", "fedora"], facts.distro_like);
+        assert!(facts.is_like("rhel"));
+        assert!(facts.is_like("rocky"));
+        assert!(!facts.is_like("debian"));
+        assert_eq!(Some("9.4"), facts.distro_version.as_deref());
+        assert_eq!(
+            Some("Rocky Linux 9.4 (Blue Onyx)"),
+            facts.distro_name.as_deref()
+        );
+        assert_eq!("6.8.0-45-generic", facts.kernel);
+        assert_eq!("x86_64", facts.architecture);
+        assert_eq!("web-1", facts.hostname);
+        assert_eq!(InitSystem::Systemd, facts.init_system);
+
+        let facts = Facts::parse(
+            "kernel_name=Linux\nkernel_release=6.6.31-0-lts\nmachine=aarch64\n\
+             hostname=edge\ninit=openrc\nmacos_version=\n\
+             NAME=\"Alpine Linux\"\nID=alpine\nVERSION_ID=3.20.1\n",
+        );
+        assert_eq!(Some("alpine"), facts.distro.as_deref());
+        assert!(facts.distro_like.is_empty());
+        assert_eq!(None, facts.distro_name);
+        assert_eq!(InitSystem::OpenRc, facts.init_system);
+    }
+
+    #[test]
+    fn other_facts() {
+        let facts = Facts::parse(
+            "kernel_name=Darwin\nkernel_release=23.6.0\nmachine=arm64\n\
+             hostname=mac.local\ninit=launchd\nmacos_version=14.6.1\n",
+        );
+        assert_eq!(OsFamily::MacOs, facts.os_family);
+        assert_eq!(Some("macos"), facts.distro.as_deref());
+        assert_eq!(Some("14.6.1"), facts.distro_version.as_deref());
+        assert_eq!(InitSystem::Launchd, facts.init_system);
+
+        let facts = Facts::parse("kernel_name=Haiku\ninit=\n");
+        assert_eq!(OsFamily::Other("Haiku".to_string()), facts.os_family);
+        assert_eq!(InitSystem::Other(String::new()), facts.init_system);
+        assert_eq!(None, facts.distro);
+    }
+
+    #[test]
+    fn unquoting() {
+        assert_eq!("plain", unquote("plain"));
+        assert_eq!("a b", unquote("\"a b\""));
+        assert_eq!(r#"say "hi" \o/"#, unquote(r#""say \"hi\" \\o/""#));
+        assert_eq!(r"it\s", unquote(r"'it\s'"));
+        assert_eq!("\"", unquote("\""));
+    }
+}
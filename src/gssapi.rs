@@ -0,0 +1,387 @@
+//! Kerberos authentification through the system GSSAPI library (MIT `libgssapi_krb5`).
+//!
+//! The `libgssapi` crate does not expose `gss_get_mic`, which `gssapi-with-mic`
+//! needs to sign the session, so the few required functions are bound here directly.
+
+use russh::{GssapiAuthenticator, GssapiError, GssapiStep, SendError};
+use std::ffi::c_void;
+use std::ptr;
+
+#[allow(non_camel_case_types)]
+type OM_uint32 = u32;
+#[allow(non_camel_case_types)]
+type gss_name_t = *mut c_void;
+#[allow(non_camel_case_types)]
+type gss_ctx_id_t = *mut c_void;
+
+#[repr(C)]
+struct GssBuffer {
+    length: usize,
+    value: *mut c_void,
+}
+
+#[repr(C)]
+struct GssOid {
+    length: OM_uint32,
+    elements: *const c_void,
+}
+
+#[link(name = "gssapi_krb5")]
+extern "C" {
+    fn gss_import_name(
+        minor_status: *mut OM_uint32,
+        input_name_buffer: *const GssBuffer,
+        input_name_type: *const GssOid,
+        output_name: *mut gss_name_t,
+    ) -> OM_uint32;
+    fn gss_release_name(minor_status: *mut OM_uint32, name: *mut gss_name_t) -> OM_uint32;
+    #[allow(clippy::too_many_arguments)]
+    fn gss_init_sec_context(
+        minor_status: *mut OM_uint32,
+        initiator_cred_handle: *const c_void,
+        context_handle: *mut gss_ctx_id_t,
+        target_name: gss_name_t,
+        mech_type: *const GssOid,
+        req_flags: OM_uint32,
+        time_req: OM_uint32,
+        input_chan_bindings: *const c_void,
+        input_token: *const GssBuffer,
+        actual_mech_type: *mut *const GssOid,
+        output_token: *mut GssBuffer,
+        ret_flags: *mut OM_uint32,
+        time_rec: *mut OM_uint32,
+    ) -> OM_uint32;
+    fn gss_get_mic(
+        minor_status: *mut OM_uint32,
+        context_handle: gss_ctx_id_t,
+        qop_req: OM_uint32,
+        message_buffer: *const GssBuffer,
+        message_token: *mut GssBuffer,
+    ) -> OM_uint32;
+    fn gss_delete_sec_context(
+        minor_status: *mut OM_uint32,
+        context_handle: *mut gss_ctx_id_t,
+        output_token: *mut GssBuffer,
+    ) -> OM_uint32;
+    fn gss_release_buffer(minor_status: *mut OM_uint32, buffer: *mut GssBuffer) -> OM_uint32;
+    fn gss_display_status(
+        minor_status: *mut OM_uint32,
+        status_value: OM_uint32,
+        status_type: i32,
+        mech_type: *const GssOid,
+        message_context: *mut OM_uint32,
+        status_string: *mut GssBuffer,
+    ) -> OM_uint32;
+}
+
+const GSS_S_CONTINUE_NEEDED: OM_uint32 = 1;
+const GSS_C_MUTUAL_FLAG: OM_uint32 = 2;
+const GSS_C_INTEG_FLAG: OM_uint32 = 32;
+const GSS_C_GSS_CODE: i32 = 1;
+const GSS_C_MECH_CODE: i32 = 2;
+
+/// 1.2.840.113554.1.2.1.4, names of the form `service@host`.
+const NT_HOSTBASED_SERVICE: &[u8] = b"\x2a\x86\x48\x86\xf7\x12\x01\x02\x01\x04";
+/// 1.2.840.113554.1.2.2, the Kerberos 5 mechanism.
+const MECH_KRB5: &[u8] = b"\x2a\x86\x48\x86\xf7\x12\x01\x02\x02";
+
+/// The Kerberos 5 mechanism OID as DER, the way it is sent over the wire.
+pub(crate) fn krb5_mechanism() -> Vec<u8> {
+    let mut oid = vec![0x06, MECH_KRB5.len() as u8];
+    oid.extend_from_slice(MECH_KRB5);
+    oid
+}
+
+fn oid(elements: &'static [u8]) -> GssOid {
+    GssOid {
+        length: elements.len() as OM_uint32,
+        elements: elements.as_ptr().cast(),
+    }
+}
+
+fn is_error(major: OM_uint32) -> bool {
+    major & 0xffff_0000 != 0
+}
+
+/// Copies a buffer allocated by the GSSAPI library and releases it.
+fn take_buffer(mut buffer: GssBuffer) -> Vec<u8> {
+    let data = if buffer.value.is_null() {
+        Vec::new()
+    } else {
+        // SAFETY: the library returned `length` initialized bytes at `value`.
+        unsafe { std::slice::from_raw_parts(buffer.value as *const u8, buffer.length).to_vec() }
+    };
+    let mut minor = 0;
+    // SAFETY: the buffer was allocated by the library and is released once.
+    unsafe { gss_release_buffer(&mut minor, &mut buffer) };
+    data
+}
+
+fn empty_buffer() -> GssBuffer {
+    GssBuffer {
+        length: 0,
+        value: ptr::null_mut(),
+    }
+}
+
+fn input_buffer(data: &[u8]) -> GssBuffer {
+    GssBuffer {
+        length: data.len(),
+        value: data.as_ptr() as *mut c_void,
+    }
+}
+
+/// Human readable messages of a failed call, e.g. about missing credentials.
+fn status_message(major: OM_uint32, minor: OM_uint32) -> String {
+    let mut messages = Vec::new();
+    for (status, status_type) in [(major, GSS_C_GSS_CODE), (minor, GSS_C_MECH_CODE)] {
+        let mut message_context = 0;
+        loop {
+            let mut minor = 0;
+            let mut message = empty_buffer();
+            // SAFETY: all pointers are valid for the duration of the call.
+            let major = unsafe {
+                gss_display_status(
+                    &mut minor,
+                    status,
+                    status_type,
+                    ptr::null(),
+                    &mut message_context,
+                    &mut message,
+                )
+            };
+            if is_error(major) {
+                break;
+            }
+            let message = take_buffer(message);
+            messages.push(String::from_utf8_lossy(&message).into_owned());
+            if message_context == 0 {
+                break;
+            }
+        }
+    }
+    messages.join(", ")
+}
+
+#[derive(Debug)]
+pub(crate) enum GssapiAuthError {
+    Send,
+    Gssapi(String),
+}
+
+impl From<SendError> for GssapiAuthError {
+    fn from(_: SendError) -> Self {
+        Self::Send
+    }
+}
+
+impl From<GssapiAuthError> for crate::Error {
+    fn from(error: GssapiAuthError) -> Self {
+        match error {
+            GssapiAuthError::Send => crate::Error::SshError(russh::Error::SendError),
+            GssapiAuthError::Gssapi(message) => crate::Error::GssapiFailed(message),
+        }
+    }
+}
+
+/// A Kerberos security context for the `host` service of a server, using the
+/// credentials of the default credential cache, e.g. from `kinit`.
+pub(crate) struct KerberosAuthenticator {
+    target: gss_name_t,
+    context: gss_ctx_id_t,
+    server_error: Option<String>, // the GSSAPI error reported by the server
+}
+
+// SAFETY: the handles are only used through `&mut self`, never concurrently.
+unsafe impl Send for KerberosAuthenticator {}
+
+impl KerberosAuthenticator {
+    pub(crate) fn new(host: &str) -> Result<Self, GssapiAuthError> {
+        let service = format!("host@{host}");
+        let name_type = oid(NT_HOSTBASED_SERVICE);
+        let mut minor = 0;
+        let mut target = ptr::null_mut();
+        // SAFETY: all pointers are valid for the duration of the call.
+        let major = unsafe {
+            gss_import_name(
+                &mut minor,
+                &input_buffer(service.as_bytes()),
+                &name_type,
+                &mut target,
+            )
+        };
+        if is_error(major) {
+            return Err(GssapiAuthError::Gssapi(status_message(major, minor)));
+        }
+        Ok(Self {
+            target,
+            context: ptr::null_mut(),
+            server_error: None,
+        })
+    }
+
+    /// Why the server rejected the authentification, the GSSAPI error it
+    /// reported if any.
+    pub(crate) fn failure_message(&mut self) -> String {
+        self.server_error
+            .take()
+            .unwrap_or_else(|| "the server rejected the Kerberos credentials".to_string())
+    }
+
+    /// Advances the security context, returning the token for the server and
+    /// whether the context is established.
+    fn init(&mut self, input_token: Option<&[u8]>) -> Result<(Vec<u8>, bool), GssapiAuthError> {
+        let mech = oid(MECH_KRB5);
+        let input_token = input_token.map(input_buffer);
+        let mut output_token = empty_buffer();
+        let mut minor = 0;
+        // SAFETY: all pointers are valid for the duration of the call, the
+        // context handle is owned by `self`.
+        let major = unsafe {
+            gss_init_sec_context(
+                &mut minor,
+                ptr::null(),
+                &mut self.context,
+                self.target,
+                &mech,
+                GSS_C_MUTUAL_FLAG | GSS_C_INTEG_FLAG,
+                0,
+                ptr::null(),
+                input_token.as_ref().map_or(ptr::null(), |t| t as *const _),
+                ptr::null_mut(),
+                &mut output_token,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        let token = take_buffer(output_token);
+        if is_error(major) {
+            return Err(GssapiAuthError::Gssapi(status_message(major, minor)));
+        }
+        Ok((token, major & GSS_S_CONTINUE_NEEDED == 0))
+    }
+
+    /// Signs `data` with the established context.
+    fn mic(&mut self, data: &[u8]) -> Result<Vec<u8>, GssapiAuthError> {
+        let mut token = empty_buffer();
+        let mut minor = 0;
+        // SAFETY: all pointers are valid for the duration of the call.
+        let major =
+            unsafe { gss_get_mic(&mut minor, self.context, 0, &input_buffer(data), &mut token) };
+        let token = take_buffer(token);
+        if is_error(major) {
+            return Err(GssapiAuthError::Gssapi(status_message(major, minor)));
+        }
+        Ok(token)
+    }
+}
+
+impl Drop for KerberosAuthenticator {
+    fn drop(&mut self) {
+        let mut minor = 0;
+        // SAFETY: the handles are owned by `self` and released exactly once.
+        unsafe {
+            if !self.context.is_null() {
+                gss_delete_sec_context(&mut minor, &mut self.context, ptr::null_mut());
+            }
+            gss_release_name(&mut minor, &mut self.target);
+        }
+    }
+}
+
+impl GssapiAuthenticator for KerberosAuthenticator {
+    type Error = GssapiAuthError;
+
+    async fn gssapi_step(
+        &mut self,
+        selected_mechanism: Option<Vec<u8>>,
+        input_token: Option<Vec<u8>>,
+        mic_data: Vec<u8>,
+    ) -> Result<GssapiStep, Self::Error> {
+        if selected_mechanism.is_some_and(|mechanism| mechanism != krb5_mechanism()) {
+            return Err(GssapiAuthError::Gssapi(
+                "server selected an unsupported mechanism".to_string(),
+            ));
+        }
+        let (token, established) = self.init(input_token.as_deref())?;
+        if !established {
+            return Ok(GssapiStep::Continue { token });
+        }
+        Ok(GssapiStep::Complete {
+            token: (!token.is_empty()).then_some(token),
+            mic: Some(self.mic(&mic_data)?),
+        })
+    }
+
+    async fn gssapi_error(&mut self, error: GssapiError) {
+        self.server_error = Some(match error {
+            GssapiError::Status {
+                major_status,
+                minor_status,
+                message,
+            } => {
+                if message.is_empty() {
+                    status_message(major_status, minor_status)
+                } else {
+                    message
+                }
+            }
+            // The token is for the local mechanism, which turns it into the
+            // error of the server.
+            GssapiError::ErrorToken(token) => match self.init(Some(&token)) {
+                Err(GssapiAuthError::Gssapi(message)) => message,
+                _ => "the server sent an undecodable GSSAPI error token".to_string(),
+            },
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn krb5_mechanism_der() {
+        assert_eq!(
+            b"\x06\x09\x2a\x86\x48\x86\xf7\x12\x01\x02\x02".to_vec(),
+            krb5_mechanism()
+        );
+    }
+
+    #[test]
+    fn init_without_ticket() {
+        let mut authenticator = KerberosAuthenticator::new("ssh.invalid").unwrap();
+        match authenticator.init(None) {
+            Err(GssapiAuthError::Gssapi(message)) => assert!(!message.is_empty()),
+            _ => panic!("Expected a GSSAPI error without a ticket for ssh.invalid"),
+        }
+    }
+
+    #[tokio::test]
+    async fn server_errors() {
+        let mut authenticator = KerberosAuthenticator::new("ssh.invalid").unwrap();
+        assert_eq!(
+            "the server rejected the Kerberos credentials",
+            authenticator.failure_message()
+        );
+
+        let error = GssapiError::Status {
+            major_status: 0x000d_0000,
+            minor_status: 0,
+            message: "Ticket expired".to_string(),
+        };
+        authenticator.gssapi_error(error).await;
+        assert_eq!("Ticket expired", authenticator.failure_message());
+
+        // Without a message the codes are looked up locally.
+        let error = GssapiError::Status {
+            major_status: 0x0007_0000,
+            minor_status: 0,
+            message: String::new(),
+        };
+        authenticator.gssapi_error(error).await;
+        let message = authenticator.failure_message();
+        assert!(!message.is_empty());
+        assert_ne!("the server rejected the Kerberos credentials", message);
+    }
+}
@@ -0,0 +1,514 @@
+//! Reading and editing OpenSSH known_hosts files, e.g. to record the keys of
+//! freshly provisioned servers or to forget a reinstalled one like
+//! `ssh-keygen -R` does.
+//!
+//! Lines which aren't entries, like comments, are kept as they are when the
+//! file is written back, and so are the entries which weren't removed.
+//!
+//! ```no_run
+//! use async_ssh2_tokio::known_hosts::{Entry, KnownHosts};
+//!
+//! let path = KnownHosts::default_path()?;
+//! let mut known_hosts = KnownHosts::read(&path)?;
+//! known_hosts.remove_host("10.10.10.2", 22);
+//! let key = russh::keys::parse_public_key_base64(
+//!     "AAAAC3NzaC1lZDI1NTE5AAAAIJdD7y3aLq454yWBdwLWbieU1ebz9/cu7/QEXn9OIeZJ",
+//! )?;
+//! known_hosts.add(Entry::new(["10.10.10.2"], 22, key, true)?);
+//! known_hosts.write(&path)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use hmac::{Hmac, KeyInit, Mac};
+use russh::keys::PublicKey;
+use sha1::Sha1;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The marker in front of a known_hosts line whose key isn't the key of a host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Marker {
+    CertAuthority, // `@cert-authority`, the key signs the certificates of the hosts
+    Revoked,       // `@revoked`, the key is rejected for the hosts
+}
+
+impl Marker {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::CertAuthority => "@cert-authority",
+            Self::Revoked => "@revoked",
+        }
+    }
+}
+
+/// A line of a known_hosts file listing a key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Entry {
+    /// `None` for the key of a host.
+    pub marker: Option<Marker>,
+    /// The host patterns, which may be hashed (`|1|<salt>|<hash>`), contain
+    /// `*` and `?` wildcards or be negated by `!`. Hosts on another port than
+    /// 22 are listed as `[host]:port`.
+    pub patterns: Vec<String>,
+    /// The key, with the comment at the end of the line if any.
+    pub key: PublicKey,
+}
+
+impl Entry {
+    /// The entry of a host known under each of `hosts`, e.g. its name and IP
+    /// address, hashed like by `ssh-keygen -H` if `hashed` is set.
+    pub fn new<'a>(
+        hosts: impl IntoIterator<Item = &'a str>,
+        port: u16,
+        key: PublicKey,
+        hashed: bool,
+    ) -> Result<Self, crate::Error> {
+        let mut patterns = Vec::new();
+        for host in hosts {
+            let host = host_port(host, port);
+            patterns.push(if hashed { hash_host(&host)? } else { host });
+        }
+        Ok(Self {
+            marker: None,
+            patterns,
+            key,
+        })
+    }
+
+    /// A `@cert-authority` entry trusting the host certificates signed by `key`
+    /// for the hosts matching the comma separated `patterns`, e.g. `*.example.com`.
+    pub fn cert_authority(patterns: &str, key: PublicKey) -> Self {
+        Self {
+            marker: Some(Marker::CertAuthority),
+            patterns: patterns.split(',').map(str::to_string).collect(),
+            key,
+        }
+    }
+
+    /// A `@revoked` entry rejecting `key` for the hosts matching the comma
+    /// separated `patterns`, `*` for all of them.
+    pub fn revoked(patterns: &str, key: PublicKey) -> Self {
+        Self {
+            marker: Some(Marker::Revoked),
+            patterns: patterns.split(',').map(str::to_string).collect(),
+            key,
+        }
+    }
+
+    /// Whether the entry applies to `host` on `port`.
+    pub fn matches(&self, host: &str, port: u16) -> bool {
+        patterns_match(&host_port(host, port), &self.patterns)
+    }
+
+    /// Whether the host patterns are hashed.
+    pub fn is_hashed(&self) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| pattern.trim_start_matches('!').starts_with("|1|"))
+    }
+}
+
+impl FromStr for Entry {
+    type Err = crate::Error;
+
+    /// Parses a line of a known_hosts file, `[marker] patterns key-type key [comment]`.
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let invalid = || crate::Error::KnownHostEntryInvalid(line.to_string());
+        let line = line.trim();
+        let (marker, rest) = match line.split_once(char::is_whitespace) {
+            Some(("@cert-authority", rest)) => (Some(Marker::CertAuthority), rest),
+            Some(("@revoked", rest)) => (Some(Marker::Revoked), rest),
+            Some((marker, _)) if marker.starts_with('@') => return Err(invalid()),
+            _ => (None, line),
+        };
+        let (patterns, key) = rest
+            .trim_start()
+            .split_once(char::is_whitespace)
+            .ok_or_else(invalid)?;
+        let key = PublicKey::from_openssh(key.trim_start()).map_err(|_| invalid())?;
+        Ok(Self {
+            marker,
+            patterns: patterns.split(',').map(str::to_string).collect(),
+            key,
+        })
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(marker) = self.marker {
+            write!(f, "{} ", marker.as_str())?;
+        }
+        let key = self.key.to_openssh().map_err(|_| fmt::Error)?;
+        write!(f, "{} {key}", self.patterns.join(","))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Line {
+    Entry(Entry, Option<String>), // the line it was parsed from, written back unchanged
+    Other(String),                // comments, blank and unparsable lines
+}
+
+/// The contents of a known_hosts file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KnownHosts {
+    lines: Vec<Line>,
+}
+
+impl KnownHosts {
+    /// The known_hosts file of the user, `~/.ssh/known_hosts`.
+    pub fn default_path() -> Result<PathBuf, crate::Error> {
+        let home = std::env::home_dir().ok_or_else(|| crate::Error::HostKeyFileFailed {
+            path: "~/.ssh/known_hosts".to_string(),
+            reason: "the home directory is unknown".to_string(),
+        })?;
+        Ok(home.join(".ssh").join("known_hosts"))
+    }
+
+    /// Parses the contents of a known_hosts file. Lines which aren't valid
+    /// entries are ignored, like by the `ssh` binary, but kept.
+    pub fn parse(contents: &str) -> Self {
+        let lines = contents
+            .lines()
+            .map(|line| {
+                let trimmed = line.trim_start();
+                if trimmed.is_empty() || trimmed.starts_with('#') {
+                    return Line::Other(line.to_string());
+                }
+                match line.parse() {
+                    Ok(entry) => Line::Entry(entry, Some(line.to_string())),
+                    Err(_) => Line::Other(line.to_string()),
+                }
+            })
+            .collect();
+        Self { lines }
+    }
+
+    /// Reads a known_hosts file, a missing file being empty.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, crate::Error> {
+        let path = path.as_ref();
+        match std::fs::read_to_string(path) {
+            Ok(contents) => Ok(Self::parse(&contents)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(file_failed(path, e)),
+        }
+    }
+
+    /// Writes the file, creating its directory if needed.
+    ///
+    /// The file is replaced by renaming a temporary copy, so readers never see
+    /// it half written. Its permissions are preserved.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), crate::Error> {
+        let path = path.as_ref();
+        self.write_atomically(path)
+            .map_err(|e| file_failed(path, e))
+    }
+
+    fn write_atomically(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(format!(".{}.tmp", std::process::id()));
+        let mut file = std::fs::File::create(&temporary)?;
+        if let Ok(metadata) = std::fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.write_all(self.to_string().as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&temporary, path)
+    }
+
+    /// All entries, in the order of the file.
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Entry(entry, _) => Some(entry),
+            Line::Other(_) => None,
+        })
+    }
+
+    /// The entries applying to `host` on `port`, including the
+    /// `@cert-authority` and `@revoked` ones.
+    pub fn lookup<'a>(&'a self, host: &'a str, port: u16) -> impl Iterator<Item = &'a Entry> {
+        self.entries()
+            .filter(move |entry| entry.matches(host, port))
+    }
+
+    /// Appends an entry.
+    pub fn add(&mut self, entry: Entry) {
+        self.lines.push(Line::Entry(entry, None));
+    }
+
+    /// Removes the keys of `host` on `port` and returns them, like
+    /// `ssh-keygen -R`. The `@cert-authority` and `@revoked` entries are kept.
+    pub fn remove_host(&mut self, host: &str, port: u16) -> Vec<Entry> {
+        self.remove_where(|entry| entry.marker.is_none() && entry.matches(host, port))
+    }
+
+    /// Removes the entries of `key`, whatever their hosts or marker, and
+    /// returns them.
+    pub fn remove_key(&mut self, key: &PublicKey) -> Vec<Entry> {
+        self.remove_where(|entry| entry.key.key_data() == key.key_data())
+    }
+
+    /// Removes the entries for which `remove` returns `true` and returns them.
+    pub(crate) fn remove_where(&mut self, mut remove: impl FnMut(&Entry) -> bool) -> Vec<Entry> {
+        let mut removed = Vec::new();
+        self.lines.retain(|line| match line {
+            Line::Entry(entry, _) if remove(entry) => {
+                removed.push(entry.clone());
+                false
+            }
+            _ => true,
+        });
+        removed
+    }
+}
+
+impl fmt::Display for KnownHosts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Entry(_, Some(line)) | Line::Other(line) => writeln!(f, "{line}")?,
+                Line::Entry(entry, None) => writeln!(f, "{entry}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The error for a known_hosts, public or revoked key file which can't be read,
+/// parsed or written.
+pub(crate) fn file_failed(path: &Path, reason: impl fmt::Display) -> crate::Error {
+    crate::Error::HostKeyFileFailed {
+        path: path.display().to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+/// `host` as listed in known_hosts files, `[host]:port` if `port` isn't 22.
+pub(crate) fn host_port(host: &str, port: u16) -> String {
+    if port == 22 {
+        host.to_string()
+    } else {
+        format!("[{host}]:{port}")
+    }
+}
+
+/// Whether `host` matches the host patterns of a known_hosts line, none of the
+/// negated ones matching it.
+fn patterns_match(host: &str, patterns: &[String]) -> bool {
+    let mut matched = false;
+    for pattern in patterns {
+        let (negated, pattern) = match pattern.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern.as_str()),
+        };
+        let matches = pattern_matches(host, pattern);
+        if matches && negated {
+            return false;
+        }
+        matched |= matches;
+    }
+    matched
+}
+
+/// Whether `host` matches a single host pattern, which may be hashed or
+/// contain `*` and `?` wildcards.
+pub(crate) fn pattern_matches(host: &str, pattern: &str) -> bool {
+    match pattern.strip_prefix("|1|") {
+        Some(hashed) => hashed_host_matches(host, hashed),
+        None => wildcard_matches(host.as_bytes(), pattern.as_bytes()),
+    }
+}
+
+/// Whether `host` matches a `<salt>|<hash>` hashed known_hosts pattern.
+fn hashed_host_matches(host: &str, hashed: &str) -> bool {
+    let Some((salt, hash)) = hashed.split_once('|') else {
+        return false;
+    };
+    let (Ok(salt), Ok(hash)) = (
+        data_encoding::BASE64.decode(salt.as_bytes()),
+        data_encoding::BASE64.decode(hash.as_bytes()),
+    ) else {
+        return false;
+    };
+    <Hmac<Sha1> as KeyInit>::new_from_slice(&salt)
+        .map(|mac| mac.chain_update(host).verify_slice(&hash).is_ok())
+        .unwrap_or(false)
+}
+
+fn wildcard_matches(text: &[u8], pattern: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| wildcard_matches(&text[skip..], rest)),
+        Some((&c, rest)) => match text.split_first() {
+            Some((&t, text)) => {
+                (c == b'?' || c.eq_ignore_ascii_case(&t)) && wildcard_matches(text, rest)
+            }
+            None => false,
+        },
+    }
+}
+
+/// Hashes the host name of a known_hosts entry with a random salt, the
+/// `|1|<salt>|<HMAC-SHA1 of host>` format of `ssh-keygen -H`.
+fn hash_host(host: &str) -> Result<String, crate::Error> {
+    let unhashable = |reason: &dyn fmt::Display| {
+        crate::Error::KnownHostEntryInvalid(format!("unable to hash {host}: {reason}"))
+    };
+    let mut salt = [0; 20];
+    getrandom::fill(&mut salt).map_err(|e| unhashable(&e))?;
+    let hash = <Hmac<Sha1> as KeyInit>::new_from_slice(&salt)
+        .map_err(|e| unhashable(&e))?
+        .chain_update(host)
+        .finalize()
+        .into_bytes();
+    Ok(format!(
+        "|1|{}|{}",
+        data_encoding::BASE64.encode(&salt),
+        data_encoding::BASE64.encode(&hash)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "AAAAC3NzaC1lZDI1NTE5AAAAIJdD7y3aLq454yWBdwLWbieU1ebz9/cu7/QEXn9OIeZJ";
+    const OTHER_KEY: &str = "AAAAC3NzaC1lZDI1NTE5AAAAILIG2T/B0l0gaqj3puu510tu9N1OkQ4znY3LYuEm5zCF";
+
+    fn patterns(patterns: &str) -> Vec<String> {
+        patterns.split(',').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn host_patterns() {
+        assert!(patterns_match("example.com", &patterns("example.com")));
+        assert!(patterns_match(
+            "www.example.com",
+            &patterns("*.example.com")
+        ));
+        assert!(patterns_match(
+            "[10.0.0.1]:2222",
+            &patterns("[10.0.0.?]:2222")
+        ));
+        assert!(!patterns_match("[10.0.0.1]:2222", &patterns("10.0.0.1")));
+        assert!(!patterns_match(
+            "bad.example.com",
+            &patterns("*.example.com,!bad.example.com")
+        ));
+        assert!(patterns_match(
+            "example.com",
+            &patterns("|1|O33ESRMWPVkMYIwJ1Uw+n877jTo=|nuuC5vEqXlEZ/8BXQR7m619W6Ak=")
+        ));
+    }
+
+    #[test]
+    fn parse_entries() {
+        let entry: Entry = format!("example.com,10.0.0.1 ssh-ed25519 {KEY} server key")
+            .parse()
+            .unwrap();
+        assert_eq!(None, entry.marker);
+        assert_eq!(patterns("example.com,10.0.0.1"), entry.patterns);
+        assert_eq!("server key", entry.key.comment().as_str_lossy());
+        assert!(entry.matches("10.0.0.1", 22));
+        assert!(!entry.matches("10.0.0.1", 2222));
+        assert!(!entry.is_hashed());
+
+        let entry: Entry = format!("@cert-authority *.example.com ssh-ed25519 {KEY}")
+            .parse()
+            .unwrap();
+        assert_eq!(Some(Marker::CertAuthority), entry.marker);
+        assert!(entry.matches("www.example.com", 22));
+        assert_eq!(
+            format!("@cert-authority *.example.com ssh-ed25519 {KEY}"),
+            entry.to_string()
+        );
+
+        let entry: Entry = format!(
+            "|1|O33ESRMWPVkMYIwJ1Uw+n877jTo=|nuuC5vEqXlEZ/8BXQR7m619W6Ak= ssh-ed25519 {KEY}"
+        )
+        .parse()
+        .unwrap();
+        assert!(entry.is_hashed());
+        assert!(entry.matches("example.com", 22));
+
+        assert!(matches!(
+            "@marker example.com ssh-ed25519 AAAA".parse::<Entry>(),
+            Err(crate::Error::KnownHostEntryInvalid(_))
+        ));
+        assert!("example.com".parse::<Entry>().is_err());
+        assert!("example.com ssh-ed25519 AAAA".parse::<Entry>().is_err());
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let key = russh::keys::parse_public_key_base64(KEY).unwrap();
+        let other_key = russh::keys::parse_public_key_base64(OTHER_KEY).unwrap();
+        let contents = format!(
+            "# managed by hand\n\
+             example.com   ssh-ed25519 {OTHER_KEY}\n\
+             not an entry\n\
+             @revoked * ssh-ed25519 {OTHER_KEY}\n\
+             other.com ssh-ed25519 {KEY}\n"
+        );
+        let mut known_hosts = KnownHosts::parse(&contents);
+        assert_eq!(contents, known_hosts.to_string());
+        assert_eq!(3, known_hosts.entries().count());
+        assert_eq!(2, known_hosts.lookup("example.com", 22).count());
+
+        let removed = known_hosts.remove_host("example.com", 22);
+        assert_eq!(1, removed.len());
+        assert_eq!(other_key.key_data(), removed[0].key.key_data());
+        known_hosts.add(Entry::new(["example.com", "10.0.0.1"], 2222, key.clone(), false).unwrap());
+        known_hosts.add(Entry::new(["example.com"], 22, key.clone(), true).unwrap());
+        let lookup: Vec<_> = known_hosts.lookup("example.com", 22).collect();
+        assert_eq!(2, lookup.len());
+        assert_eq!(Some(Marker::Revoked), lookup[0].marker);
+        assert!(lookup[1].is_hashed());
+        assert_eq!(2, known_hosts.lookup("10.0.0.1", 2222).count());
+        assert_eq!(
+            0,
+            known_hosts
+                .lookup("10.0.0.1", 22)
+                .filter(|entry| entry.marker.is_none())
+                .count()
+        );
+
+        assert_eq!(3, known_hosts.remove_key(&key).len());
+        assert_eq!(
+            format!(
+                "# managed by hand\n\
+                 not an entry\n\
+                 @revoked * ssh-ed25519 {OTHER_KEY}\n"
+            ),
+            known_hosts.to_string()
+        );
+    }
+
+    #[test]
+    fn read_and_write() {
+        let path = std::env::temp_dir()
+            .join(format!("known_hosts_module_{}", std::process::id()))
+            .join("known_hosts");
+        let key = russh::keys::parse_public_key_base64(KEY).unwrap();
+        let mut known_hosts = KnownHosts::read(&path).unwrap();
+        assert_eq!(0, known_hosts.entries().count());
+        known_hosts.add(Entry::cert_authority("*.example.com", key));
+        known_hosts.write(&path).unwrap();
+        let contents = std::fs::read_to_string(&path);
+        let reread = KnownHosts::read(&path);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(
+            format!("@cert-authority *.example.com ssh-ed25519 {KEY}\n"),
+            contents.unwrap()
+        );
+        assert_eq!(known_hosts.to_string(), reread.unwrap().to_string());
+    }
+}
@@ -7,7 +7,23 @@
 //! # Features
 //! * Connect to a SSH Host via IP
 //! * Execute commands on the remote host
-//! * Get the stdout and exit code of the command
+//! * Get the stdout, stderr and exit code of the command, or stream the output as it arrives
+//! * Build commands with arguments and environment variables quoted for you with [`RemoteCommand`]
+//! * Drive interactive and long-lived processes through their stdin, stdout and stderr with [`RemoteChild`]
+//! * Run commands and shells in a pseudo terminal with [`PtyRequest`]
+//! * Open a remote shell in the local terminal like `ssh` does with [`LocalTerminal`]
+//! * Drive routers and switches without exec requests by their prompt with [`PromptSession`]
+//! * Answer `sudo` passwords, confirmations and host key questions in terminal sessions with a [`PromptHandler`]
+//! * Record terminal sessions as typescript or asciicast files, see [`record`]
+//! * Keep state like the directory and variables across commands in a [`ShellSession`]
+//! * Run commands on Windows servers in PowerShell or `cmd.exe`, see [`Shell`]
+//! * Quote arguments for POSIX shells, PowerShell and `cmd.exe` with [`quote`]
+//! * Gather the operating system, distribution and init system of a host with [`Facts`]
+//! * Forward the local ssh-agent to the remote host
+//! * Authenticate with OpenSSH, PEM or PuTTY `.ppk` private keys
+//! * Fetch credentials on demand from a pluggable [`CredentialProvider`]
+//! * Inspect the server key, banner and accepted methods before authenticating with [`UnauthenticatedClient`]
+//! * Manage known_hosts files, including hashed and `@cert-authority` entries, with [`known_hosts::KnownHosts`]
 //!
 //! # Example
 //! ```no_run
@@ -20,8 +36,14 @@
 //!     // AuthMethod::with_key_file("key_file_name", None);
 //!     // or
 //!     // AuthMethod::with_key(key: &str, passphrase: Option<&str>)
+//!     // or, e.g. for a PKCS#8 DER key from a secret store
+//!     // AuthMethod::with_key_bytes(key: &[u8], passphrase: Option<&str>)
+//!     // or, to use the identities of a running ssh-agent
+//!     // AuthMethod::with_agent()
+//!     // or, for an ssh-agent listening on a non default socket
+//!     // AuthMethod::with_agent_socket("/path/to/agent.sock")
 //!     let auth_method = AuthMethod::with_password("root");
-//!     let mut client = Client::connect(
+//!     let client = Client::connect(
 //!         ("10.10.10.2", 22),
 //!         "root",
 //!         auth_method,
@@ -29,19 +51,81 @@
 //!     ).await?;
 //!
 //!     let result = client.execute("echo Hello SSH").await?;
-//!     assert_eq!(result.output, "Hello SSH\n");
+//!     assert_eq!(result.stdout, "Hello SSH\n");
 //!     assert_eq!(result.exit_status, 0);
 //!
 //!     let result = client.execute("echo Hello Again :)").await?;
-//!     assert_eq!(result.output, "Hello Again :)\n");
+//!     assert_eq!(result.stdout, "Hello Again :)\n");
 //!     assert_eq!(result.exit_status, 0);
 //!
 //!     Ok(())
 //! }
 //! ```
 
+/// Implements `Debug`, `PartialEq`, `Eq` and `Hash` for a newtype around an
+/// `Arc` of a user supplied function, so it can be part of an [`AuthMethod`].
+/// Equality and hashing go by the address of the `Arc`.
+macro_rules! handler_identity {
+    ($handler:ident) => {
+        impl std::fmt::Debug for $handler {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(stringify!($handler))
+            }
+        }
+
+        impl PartialEq for $handler {
+            fn eq(&self, other: &Self) -> bool {
+                std::sync::Arc::ptr_eq(&self.0, &other.0)
+            }
+        }
+
+        impl Eq for $handler {}
+
+        impl std::hash::Hash for $handler {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                (std::sync::Arc::as_ptr(&self.0) as *const ()).hash(state);
+            }
+        }
+    };
+}
+
+pub mod child;
 pub mod client;
+pub mod command;
+pub mod credentials;
 pub mod error;
+pub mod facts;
+#[cfg(feature = "gssapi")]
+mod gssapi;
+pub mod known_hosts;
+#[cfg(feature = "pkcs11")]
+mod pkcs11;
+mod ppk;
+pub mod prompt;
+pub mod pty;
+pub mod quote;
+pub mod record;
+pub mod secret;
+pub mod security_key;
+#[cfg(unix)]
+pub mod terminal;
+mod wire;
 
-pub use client::{AuthMethod, Client, ServerCheckMethod};
+pub use child::{ExitStatus, RemoteChild, Signal};
+pub use client::{
+    AgentKeyFilter, AuthMethod, BatchResults, ChannelReader, ChannelWriter, Client, CommandExit,
+    CommandOutput, CommandOutputLine, CommandOutputLines, CommandOutputStream, CommandResults,
+    DetachedProcess, FrameReader, FrameWriter, KeyboardInteractivePrompt, OutputDecoding,
+    OutputLimit, RemoteFileType, RemoteMetadata, RsaSignature, SequenceResults, ServerCheckMethod,
+    ServerVersion, SessionChannel, ShellSession, StrictHostKeyChecking, TailLines, ToHostAddrs,
+    UnauthenticatedClient, UnnamedAddrs,
+};
+pub use command::{LoginShell, RemoteCommand, Shell};
+pub use credentials::CredentialProvider;
 pub use error::Error;
+pub use facts::{Facts, InitSystem, OsFamily};
+pub use prompt::{PromptHandler, PromptSession};
+pub use pty::{PtyRequest, PtyResizer};
+pub use secret::Secret;
+#[cfg(unix)]
+pub use terminal::LocalTerminal;
@@ -0,0 +1,374 @@
+//! Authentification with keys held in a PKCS#11 token, e.g. a HSM or the PIV
+//! applet of a YubiKey.
+//!
+//! The private keys never leave the token, the signatures are made by the
+//! PKCS#11 module, like `ssh -I` does. Only RSA and NIST ECDSA keys are supported.
+
+use crate::wire::put_string;
+use cryptoki::context::{CInitializeArgs, CInitializeFlags, Pkcs11};
+use cryptoki::error::{Error as CryptokiError, RvError};
+use cryptoki::mechanism::Mechanism;
+use cryptoki::object::{Attribute, AttributeType, KeyType, ObjectClass, ObjectHandle};
+use cryptoki::session::{Session, UserType};
+use cryptoki::types::AuthPin;
+use russh::keys::agent::AgentIdentity;
+use russh::keys::{HashAlg, PublicKey};
+use russh::{SendError, Signer};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+/// DER of the `namedCurve` OIDs of the supported curves, as found in `CKA_EC_PARAMS`,
+/// with the length of their uncompressed points.
+const CURVES: [(&[u8], &str, usize); 3] = [
+    (b"\x06\x08\x2a\x86\x48\xce\x3d\x03\x01\x07", "nistp256", 65),
+    (b"\x06\x05\x2b\x81\x04\x00\x22", "nistp384", 97),
+    (b"\x06\x05\x2b\x81\x04\x00\x23", "nistp521", 133),
+];
+
+/// DER `DigestInfo` prefixes for `CKM_RSA_PKCS` signatures, see RFC 8017 section 9.2.
+const SHA1_DIGEST_INFO: &[u8] = b"\x30\x21\x30\x09\x06\x05\x2b\x0e\x03\x02\x1a\x05\x00\x04\x14";
+const SHA256_DIGEST_INFO: &[u8] =
+    b"\x30\x31\x30\x0d\x06\x09\x60\x86\x48\x01\x65\x03\x04\x02\x01\x05\x00\x04\x20";
+const SHA512_DIGEST_INFO: &[u8] =
+    b"\x30\x51\x30\x0d\x06\x09\x60\x86\x48\x01\x65\x03\x04\x02\x03\x05\x00\x04\x40";
+
+#[derive(Debug)]
+pub(crate) enum Pkcs11AuthError {
+    Send,
+    Pkcs11(String),
+}
+
+impl From<SendError> for Pkcs11AuthError {
+    fn from(_: SendError) -> Self {
+        Self::Send
+    }
+}
+
+impl From<CryptokiError> for Pkcs11AuthError {
+    fn from(error: CryptokiError) -> Self {
+        Self::Pkcs11(error.to_string())
+    }
+}
+
+impl From<Pkcs11AuthError> for crate::Error {
+    fn from(error: Pkcs11AuthError) -> Self {
+        match error {
+            Pkcs11AuthError::Send => crate::Error::SshError(russh::Error::SendError),
+            Pkcs11AuthError::Pkcs11(message) => crate::Error::Pkcs11Failed(message),
+        }
+    }
+}
+
+struct Pkcs11Key {
+    session: usize,
+    handle: ObjectHandle,
+    public_key: PublicKey,
+}
+
+/// The signing keys of all tokens of a PKCS#11 module.
+pub(crate) struct Pkcs11Token {
+    sessions: Vec<Session>,
+    keys: Vec<Pkcs11Key>,
+}
+
+impl Pkcs11Token {
+    /// Loads the module at `module_path` and logs into each token with `pin`.
+    pub(crate) fn open(module_path: &str, pin: Option<&str>) -> Result<Self, Pkcs11AuthError> {
+        let pkcs11 = Pkcs11::new(module_path)?;
+        match pkcs11.initialize(CInitializeArgs::new(CInitializeFlags::OS_LOCKING_OK)) {
+            Err(CryptokiError::Pkcs11(RvError::CryptokiAlreadyInitialized, _)) | Ok(()) => {}
+            Err(error) => return Err(error.into()),
+        }
+
+        let mut token = Self {
+            sessions: Vec::new(),
+            keys: Vec::new(),
+        };
+        for slot in pkcs11.get_slots_with_token()? {
+            let session = pkcs11.open_ro_session(slot)?;
+            if let Some(pin) = pin {
+                match session.login(UserType::User, Some(&AuthPin::from(pin))) {
+                    Err(CryptokiError::Pkcs11(RvError::UserAlreadyLoggedIn, _)) | Ok(()) => {}
+                    Err(error) => return Err(error.into()),
+                }
+            }
+            for handle in session.find_objects(&[
+                Attribute::Class(ObjectClass::PRIVATE_KEY),
+                Attribute::Sign(true),
+            ])? {
+                if let Some(public_key) = public_key(&session, handle)? {
+                    token.keys.push(Pkcs11Key {
+                        session: token.sessions.len(),
+                        handle,
+                        public_key,
+                    });
+                }
+            }
+            token.sessions.push(session);
+        }
+        Ok(token)
+    }
+
+    pub(crate) fn public_keys(&self) -> impl Iterator<Item = &PublicKey> {
+        self.keys.iter().map(|key| &key.public_key)
+    }
+}
+
+/// Reads the public key of a private key object, from the matching public key
+/// object if the token has one.
+fn public_key(
+    session: &Session,
+    private: ObjectHandle,
+) -> Result<Option<PublicKey>, CryptokiError> {
+    let mut id = None;
+    let mut key_type = None;
+    for attribute in
+        session.get_attributes(private, &[AttributeType::Id, AttributeType::KeyType])?
+    {
+        match attribute {
+            Attribute::Id(value) => id = Some(value),
+            Attribute::KeyType(value) => key_type = Some(value),
+            _ => {}
+        }
+    }
+    let public = match id {
+        Some(id) => session
+            .find_objects(&[Attribute::Class(ObjectClass::PUBLIC_KEY), Attribute::Id(id)])?
+            .into_iter()
+            .next(),
+        None => None,
+    };
+    let object = public.unwrap_or(private);
+
+    let attributes = match key_type {
+        Some(KeyType::RSA) => [AttributeType::PublicExponent, AttributeType::Modulus],
+        Some(KeyType::EC) => [AttributeType::EcParams, AttributeType::EcPoint],
+        _ => return Ok(None),
+    };
+    let values: Vec<Vec<u8>> = session
+        .get_attributes(object, &attributes)?
+        .into_iter()
+        .filter_map(|attribute| match attribute {
+            Attribute::PublicExponent(value)
+            | Attribute::Modulus(value)
+            | Attribute::EcParams(value)
+            | Attribute::EcPoint(value) => Some(value),
+            _ => None,
+        })
+        .collect();
+    let blob = match (key_type, values.as_slice()) {
+        (Some(KeyType::RSA), [e, n]) => rsa_public_blob(e, n),
+        (Some(KeyType::EC), [params, point]) => match ecdsa_public_blob(params, point) {
+            Some(blob) => blob,
+            None => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    Ok(PublicKey::from_bytes(&blob).ok())
+}
+
+/// Encodes an unsigned big-endian integer as ssh `mpint`.
+fn put_mpint(buffer: &mut Vec<u8>, data: &[u8]) {
+    let data = &data[data.iter().take_while(|&&b| b == 0).count()..];
+    if data.first().is_some_and(|&b| b & 0x80 != 0) {
+        let mut padded = vec![0];
+        padded.extend_from_slice(data);
+        put_string(buffer, &padded);
+    } else {
+        put_string(buffer, data);
+    }
+}
+
+fn rsa_public_blob(e: &[u8], n: &[u8]) -> Vec<u8> {
+    let mut blob = Vec::new();
+    put_string(&mut blob, b"ssh-rsa");
+    put_mpint(&mut blob, e);
+    put_mpint(&mut blob, n);
+    blob
+}
+
+fn ecdsa_public_blob(params: &[u8], point: &[u8]) -> Option<Vec<u8>> {
+    let (_, curve, point_len) = CURVES.iter().find(|(oid, ..)| *oid == params)?;
+    let mut blob = Vec::new();
+    put_string(&mut blob, format!("ecdsa-sha2-{curve}").as_bytes());
+    put_string(&mut blob, curve.as_bytes());
+    put_string(&mut blob, unwrap_ec_point(point, *point_len)?);
+    Some(blob)
+}
+
+/// The uncompressed point of `point_len` bytes in `CKA_EC_POINT`, which is a DER
+/// `OCTET STRING` holding it. Some modules return the raw point though, which
+/// the wrapped one is always longer than.
+fn unwrap_ec_point(point: &[u8], point_len: usize) -> Option<&[u8]> {
+    let point = if point.len() == point_len {
+        point
+    } else {
+        der_octet_string(point)?
+    };
+    (point.len() == point_len && point[0] == 0x04).then_some(point)
+}
+
+/// The contents of the DER `OCTET STRING` which is all of `der`.
+fn der_octet_string(der: &[u8]) -> Option<&[u8]> {
+    let (&0x04, rest) = der.split_first()? else {
+        return None;
+    };
+    let (&len, rest) = rest.split_first()?;
+    let (len, contents) = match len {
+        0..=0x7f => (len as usize, rest),
+        0x81 => {
+            let (&len, rest) = rest.split_first()?;
+            (len as usize, rest)
+        }
+        0x82 => {
+            let (len, rest) = rest.split_first_chunk::<2>()?;
+            (u16::from_be_bytes(*len) as usize, rest)
+        }
+        _ => return None,
+    };
+    (contents.len() == len).then_some(contents)
+}
+
+/// Creates the ssh signature blob for `data`, signed with `key` on the token.
+fn sign(
+    session: &Session,
+    key: &Pkcs11Key,
+    hash_alg: Option<HashAlg>,
+    data: &[u8],
+) -> Result<Vec<u8>, CryptokiError> {
+    let algorithm = key.public_key.algorithm();
+    let mut blob = Vec::new();
+    if key.public_key.key_data().is_rsa() {
+        let (name, mut digest_info) = match hash_alg {
+            Some(HashAlg::Sha256) => ("rsa-sha2-256", SHA256_DIGEST_INFO.to_vec()),
+            Some(HashAlg::Sha512) => ("rsa-sha2-512", SHA512_DIGEST_INFO.to_vec()),
+            _ => ("ssh-rsa", SHA1_DIGEST_INFO.to_vec()),
+        };
+        match hash_alg {
+            Some(HashAlg::Sha256) => digest_info.extend(Sha256::digest(data)),
+            Some(HashAlg::Sha512) => digest_info.extend(Sha512::digest(data)),
+            _ => digest_info.extend(Sha1::digest(data)),
+        }
+        let signature = session.sign(&Mechanism::RsaPkcs, key.handle, &digest_info)?;
+        put_string(&mut blob, name.as_bytes());
+        put_string(&mut blob, &signature);
+    } else {
+        let name = algorithm.as_str();
+        let digest = match name {
+            "ecdsa-sha2-nistp256" => Sha256::digest(data).to_vec(),
+            "ecdsa-sha2-nistp384" => Sha384::digest(data).to_vec(),
+            _ => Sha512::digest(data).to_vec(),
+        };
+        let signature = session.sign(&Mechanism::Ecdsa, key.handle, &digest)?;
+        put_string(&mut blob, name.as_bytes());
+        put_string(&mut blob, &ecdsa_signature(&signature));
+    }
+    Ok(blob)
+}
+
+/// Converts the `r || s` output of `CKM_ECDSA` to the ssh encoding of two `mpint`s.
+fn ecdsa_signature(signature: &[u8]) -> Vec<u8> {
+    let (r, s) = signature.split_at(signature.len() / 2);
+    let mut encoded = Vec::new();
+    put_mpint(&mut encoded, r);
+    put_mpint(&mut encoded, s);
+    encoded
+}
+
+impl Signer for Pkcs11Token {
+    type Error = Pkcs11AuthError;
+
+    async fn auth_sign(
+        &mut self,
+        key: &AgentIdentity,
+        hash_alg: Option<HashAlg>,
+        mut to_sign: Vec<u8>,
+    ) -> Result<Vec<u8>, Self::Error> {
+        let public_key = key.public_key();
+        let key = self
+            .keys
+            .iter()
+            .find(|key| key.public_key.key_data() == public_key.key_data())
+            .ok_or_else(|| Pkcs11AuthError::Pkcs11("unknown key".to_string()))?;
+        let blob = sign(&self.sessions[key.session], key, hash_alg, &to_sign)?;
+        put_string(&mut to_sign, &blob);
+        Ok(to_sign)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mpint_encoding() {
+        let mut buffer = Vec::new();
+        put_mpint(&mut buffer, &[0x00, 0x00, 0x7f]);
+        put_mpint(&mut buffer, &[0x80, 0x01]);
+        put_mpint(&mut buffer, &[0x00]);
+        assert_eq!(
+            vec![0, 0, 0, 1, 0x7f, 0, 0, 0, 3, 0x00, 0x80, 0x01, 0, 0, 0, 0],
+            buffer
+        );
+    }
+
+    #[test]
+    fn ec_point() {
+        let raw = [0x04; 65];
+        let mut der = vec![0x04, 0x41];
+        der.extend_from_slice(&raw);
+        assert_eq!(Some(&raw[..]), unwrap_ec_point(&der, 65));
+        assert_eq!(Some(&raw[..]), unwrap_ec_point(&raw, 65));
+
+        // A raw point looking like a short DER string.
+        let mut raw = [0x01; 65];
+        raw[..2].copy_from_slice(&[0x04, 0x3f]);
+        assert_eq!(Some(&raw[..]), unwrap_ec_point(&raw, 65));
+
+        let raw = [0x04; 133];
+        let mut der = vec![0x04, 0x81, 0x85];
+        der.extend_from_slice(&raw);
+        assert_eq!(Some(&raw[..]), unwrap_ec_point(&der, 133));
+
+        assert_eq!(None, unwrap_ec_point(&der, 65));
+        assert_eq!(None, unwrap_ec_point(&der[..100], 133));
+        assert_eq!(None, unwrap_ec_point(&[0x02; 33], 65));
+    }
+
+    #[test]
+    fn ecdsa_public_key() {
+        const PUBLIC_KEY: &str = "ecdsa-sha2-nistp256 AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTYAAABBBLaZfFmiFubfNZymRtPPIBlvuTR8kn2XGiaw2US2RclnS74EReuBYEmGTAU+qGJDyKIZDAJVt8qg6AvF3fYnf6o=";
+        let expected = PublicKey::from_openssh(PUBLIC_KEY).unwrap();
+        let point = expected
+            .key_data()
+            .ecdsa()
+            .unwrap()
+            .as_sec1_bytes()
+            .to_vec();
+        let mut der = vec![0x04, point.len() as u8];
+        der.extend_from_slice(&point);
+
+        let blob = ecdsa_public_blob(CURVES[0].0, &der).unwrap();
+        assert_eq!(
+            expected.key_data(),
+            PublicKey::from_bytes(&blob).unwrap().key_data()
+        );
+        assert_eq!(None, ecdsa_public_blob(b"\x06\x03\x2b\x65\x70", &der));
+    }
+
+    #[test]
+    fn ecdsa_signature_encoding() {
+        let mut expected = Vec::new();
+        put_string(&mut expected, &[0x00, 0x80, 0x01]);
+        put_string(&mut expected, &[0x01]);
+        assert_eq!(expected, ecdsa_signature(&[0x80, 0x01, 0x00, 0x01]));
+    }
+
+    #[test]
+    fn missing_module() {
+        assert!(matches!(
+            Pkcs11Token::open("/nonexistent/pkcs11.so", None),
+            Err(Pkcs11AuthError::Pkcs11(_))
+        ));
+    }
+}
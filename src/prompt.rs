@@ -0,0 +1,495 @@
+//! Drive network devices and other hosts which only offer an interactive
+//! shell, by waiting for their prompt, see [`PromptSession`], and answer the
+//! questions programs ask in terminal sessions with a [`PromptHandler`].
+
+use crate::client::{strip_ansi_escapes, SessionChannel};
+use crate::record::{Recording, SessionRecorder};
+use crate::{Client, ExitStatus, PtyRequest, Secret};
+use regex::Regex;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+/// Time [`PromptSession::read_until_prompt`] waits for the prompt by default.
+const DEFAULT_PROMPT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Length of the last line kept to find questions in, longer lines aren't.
+const MAX_QUESTION_LEN: usize = 1024;
+
+/// Answers the questions programs ask in a terminal session, like the password
+/// prompt of `sudo`, `Are you sure? [y/N]` or the host key confirmation of a
+/// nested `ssh`, see [`Client::set_prompt_handler`].
+///
+/// Closures taking the question and returning the answer are handlers, and
+/// [`PromptAnswers`] answers from a table of patterns.
+///
+/// ```
+/// use async_ssh2_tokio::prompt::PromptHandler;
+///
+/// let handler = |question: &str| question.ends_with("[y/N]").then(|| "y".to_string());
+/// assert_eq!(handler.answer("Continue? [y/N]").unwrap().expose_secret(), "y");
+/// assert!(handler.answer("Name:").is_none());
+/// ```
+pub trait PromptHandler: Send + Sync {
+    /// The answer to `question`, the last line printed without ANSI escape
+    /// sequences and line ending, or `None` if it isn't one to answer. Asked
+    /// again as the line grows, until answered or ended by a newline.
+    fn answer(&self, question: &str) -> Option<Secret<str>>;
+}
+
+impl<F> PromptHandler for F
+where
+    F: Fn(&str) -> Option<String> + Send + Sync,
+{
+    fn answer(&self, question: &str) -> Option<Secret<str>> {
+        self(question).map(Secret::from)
+    }
+}
+
+/// A [`PromptHandler`] answering from a fixed table.
+///
+/// Each question is answered with the answer of the first regular expression
+/// matching it, others are left alone.
+///
+/// ```
+/// use async_ssh2_tokio::prompt::{PromptAnswers, PromptHandler};
+///
+/// let answers = PromptAnswers::new([
+///     (r"^\[sudo\] password for \w+: *$", "secret"),
+///     (r"\(yes/no(/\[fingerprint\])?\)\? *$", "yes"),
+///     (r"(?i)are you sure\?? \[y/N\] *$", "y"),
+/// ])?;
+/// assert_eq!(answers.answer("[sudo] password for deploy: ").unwrap().expose_secret(), "secret");
+/// assert!(answers.answer("Name:").is_none());
+/// # Ok::<(), async_ssh2_tokio::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct PromptAnswers {
+    answers: Vec<(Regex, Secret<str>)>,
+}
+
+impl PromptAnswers {
+    /// Answer questions matching the regular expressions in `answers`. Fails
+    /// with [`Error::PromptPatternInvalid`](crate::Error::PromptPatternInvalid)
+    /// if one isn't valid.
+    pub fn new<'a>(
+        answers: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Result<Self, crate::Error> {
+        let answers = answers
+            .into_iter()
+            .map(|(pattern, answer)| Ok((Regex::new(pattern)?, Secret::from(answer))))
+            .collect::<Result<Vec<_>, regex::Error>>()
+            .map_err(|e| crate::Error::PromptPatternInvalid(e.to_string()))?;
+        Ok(Self { answers })
+    }
+}
+
+impl PromptHandler for PromptAnswers {
+    fn answer(&self, question: &str) -> Option<Secret<str>> {
+        self.answers
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(question))
+            .map(|(_, answer)| answer.clone())
+    }
+}
+
+/// Watches the output of a terminal session for questions of a
+/// [`PromptHandler`].
+pub(crate) struct PromptWatcher {
+    handler: Arc<dyn PromptHandler>,
+    line: Vec<u8>,
+}
+
+impl PromptWatcher {
+    pub(crate) fn new(handler: Arc<dyn PromptHandler>) -> Self {
+        Self {
+            handler,
+            line: Vec::new(),
+        }
+    }
+
+    /// Follow the output with `data`, and return what to type if the last line
+    /// is now a question the handler answers, ending with a newline.
+    pub(crate) fn push(&mut self, data: &[u8]) -> Option<Zeroizing<Vec<u8>>> {
+        match data.iter().rposition(|&b| b == b'\n' || b == b'\r') {
+            Some(newline) => {
+                self.line.clear();
+                self.line.extend_from_slice(&data[newline + 1..]);
+            }
+            None => self.line.extend_from_slice(data),
+        }
+        if self.line.len() > MAX_QUESTION_LEN {
+            let excess = self.line.len() - MAX_QUESTION_LEN;
+            self.line.drain(..excess);
+        }
+        if self.line.is_empty() {
+            return None;
+        }
+        let answer = self.handler.answer(&clean(&self.line))?;
+        self.line.clear();
+        let mut input = Zeroizing::new(Vec::with_capacity(answer.expose_secret().len() + 1));
+        input.extend_from_slice(answer.expose_secret().as_bytes());
+        input.push(b'\n');
+        Some(input)
+    }
+}
+
+impl fmt::Debug for PromptWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PromptWatcher")
+            .field("line", &String::from_utf8_lossy(&self.line))
+            .finish_non_exhaustive()
+    }
+}
+
+/// An interactive shell in a pseudo terminal, driven by its prompt like with
+/// netmiko or scrapli.
+///
+/// Many routers and switches don't run commands with exec requests or don't
+/// send an exit status. Here a command is typed into the shell instead, and its
+/// output is what the device prints until the prompt appears again. The prompt
+/// is matched against the last line of the output, with only whitespace
+/// allowed after the match. The output has ANSI escape sequences removed and
+/// `\n` as line endings.
+///
+/// # Examples
+///
+/// ```no_run
+/// use async_ssh2_tokio::{AuthMethod, Client, PromptSession, ServerCheckMethod};
+/// #[tokio::main]
+/// async fn main() -> Result<(), async_ssh2_tokio::Error> {
+///     let client = Client::connect(
+///         ("10.10.10.1", 22),
+///         "admin",
+///         AuthMethod::with_password("admin"),
+///         ServerCheckMethod::NoCheck,
+///     ).await?;
+///
+///     let mut session = PromptSession::open(&client, r"[\w.-]+(\(config[\w-]*\))?[>#]").await?;
+///     session.send_command("terminal length 0").await?;
+///     let version = session.send_command("show version").await?;
+///     println!("{version}");
+///     session.send_command("configure terminal").await?;
+///     session.send_command("hostname edge-1").await?;
+///     session.send_command("end").await?;
+///     println!("now at {}", session.prompt());
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct PromptSession {
+    channel: SessionChannel,
+    prompt: Regex,
+    timeout: Option<Duration>,
+    buffer: Vec<u8>,
+    last_prompt: String,
+    watcher: Option<PromptWatcher>,
+    recording: Option<Recording>,
+}
+
+impl PromptSession {
+    /// Start the shell on the host of `client` and wait for the first prompt,
+    /// matched by the regular expression `prompt`.
+    ///
+    /// The terminal is 511 columns wide, so devices don't wrap long lines. The
+    /// [`PromptHandler`] of `client`, if it has one, answers questions while
+    /// waiting for the prompt, see [`Client::set_prompt_handler`]. Fails with
+    /// [`Error::PromptPatternInvalid`](crate::Error::PromptPatternInvalid) if
+    /// `prompt` isn't a valid regular expression.
+    pub async fn open(client: &Client, prompt: &str) -> Result<Self, crate::Error> {
+        Self::open_with_recording(client, prompt, None).await
+    }
+
+    /// Start the shell like [`open`](PromptSession::open), recording the session
+    /// with `recorder`, e.g. a [`Typescript`](crate::record::Typescript) as audit
+    /// trail of the changes made to a device.
+    ///
+    /// The output and the typed lines are recorded, the recording is finished by
+    /// [`close`](PromptSession::close). Once the recorder fails, the recording
+    /// ends and the next method returns
+    /// [`Error::RecordingFailed`](crate::Error::RecordingFailed).
+    pub async fn open_recorded(
+        client: &Client,
+        prompt: &str,
+        recorder: Box<dyn SessionRecorder>,
+    ) -> Result<Self, crate::Error> {
+        Self::open_with_recording(client, prompt, Some(recorder)).await
+    }
+
+    async fn open_with_recording(
+        client: &Client,
+        prompt: &str,
+        recorder: Option<Box<dyn SessionRecorder>>,
+    ) -> Result<Self, crate::Error> {
+        let prompt =
+            Regex::new(prompt).map_err(|e| crate::Error::PromptPatternInvalid(e.to_string()))?;
+        let pty = PtyRequest::new("vt100").size(511, 24);
+        let recording = match recorder {
+            Some(recorder) => Some(Recording::start(recorder, "vt100", 511, 24)?),
+            None => None,
+        };
+        let channel = client.open_shell_channel(Some(&pty)).await?;
+        let mut session = Self {
+            channel,
+            prompt,
+            timeout: Some(DEFAULT_PROMPT_TIMEOUT),
+            buffer: Vec::new(),
+            last_prompt: String::new(),
+            watcher: client.prompt_handler().map(PromptWatcher::new),
+            recording,
+        };
+        session.read_until_prompt().await?;
+        Ok(session)
+    }
+
+    /// Change the regular expression matching the prompt, e.g. before a command
+    /// entering a mode with a different one.
+    pub fn set_prompt(&mut self, prompt: &str) -> Result<(), crate::Error> {
+        self.prompt =
+            Regex::new(prompt).map_err(|e| crate::Error::PromptPatternInvalid(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Set how long to wait for the prompt, 30 seconds by default. `None` waits
+    /// forever. Waiting longer fails with
+    /// [`Error::PromptTimedOut`](crate::Error::PromptTimedOut).
+    ///
+    /// What the device prints after a timeout stays buffered for the next read,
+    /// so the output of a later command may start with that of an earlier one.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// The last prompt the device printed, e.g. `router(config)#`.
+    pub fn prompt(&self) -> &str {
+        &self.last_prompt
+    }
+
+    /// Type `command` and return its output once the prompt appears again,
+    /// without the echo of the command.
+    pub async fn send_command(&mut self, command: &str) -> Result<String, crate::Error> {
+        self.send_line(command).await?;
+        let output = self.read_until_prompt().await?;
+        Ok(without_echo(output, command))
+    }
+
+    /// Type `line` followed by a newline, without waiting for anything, e.g. to
+    /// answer a question of the device.
+    pub async fn send_line(&mut self, line: &str) -> Result<(), crate::Error> {
+        let line = format!("{line}\n");
+        self.channel.data(line.as_bytes()).await?;
+        if let Some(recording) = &mut self.recording {
+            recording.input(line.as_bytes());
+        }
+        self.recording_error()
+    }
+
+    /// Read until the prompt appears, and return what was printed before it.
+    ///
+    /// Fails with [`Error::ShellSessionEnded`](crate::Error::ShellSessionEnded)
+    /// if the shell exits before.
+    pub async fn read_until_prompt(&mut self) -> Result<String, crate::Error> {
+        let timeout = self.timeout;
+        let read = async {
+            loop {
+                if let Some((output, prompt)) = take_until_prompt(&mut self.buffer, &self.prompt) {
+                    self.last_prompt = prompt;
+                    return Ok(output);
+                }
+                match self.channel.wait().await {
+                    Some(russh::ChannelMsg::Data { data })
+                    | Some(russh::ChannelMsg::ExtendedData { data, .. }) => {
+                        self.buffer.extend_from_slice(&data);
+                        if let Some(recording) = &mut self.recording {
+                            recording.output(&data);
+                        }
+                        if let Some(answer) = self.watcher.as_mut().and_then(|w| w.push(&data)) {
+                            self.channel.data(&answer[..]).await?;
+                            if let Some(recording) = &mut self.recording {
+                                recording.input(&answer);
+                            }
+                        }
+                        self.recording_error()?;
+                    }
+                    Some(_) => {}
+                    None => return Err(crate::Error::ShellSessionEnded),
+                }
+            }
+        };
+        match timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, read).await {
+                Ok(output) => output,
+                Err(_) => Err(crate::Error::PromptTimedOut {
+                    output: clean(&self.buffer),
+                }),
+            },
+            None => read.await,
+        }
+    }
+
+    /// Close the channel, ending the shell. Type the command leaving the shell,
+    /// like `exit` or `logout`, with [`send_line`](PromptSession::send_line)
+    /// before for a clean logout.
+    ///
+    /// Finishes the recording of a [recorded](PromptSession::open_recorded)
+    /// session with the exit status of the shell, if it sent one.
+    pub async fn close(mut self) -> Result<(), crate::Error> {
+        // Fails if the shell exited already, closing the channel.
+        let _ = self.channel.close().await;
+        let mut status = ExitStatus::Unknown;
+        while let Some(msg) = self.channel.wait().await {
+            if let Some(exit_status) = ExitStatus::from_msg(&msg) {
+                status = exit_status;
+            }
+        }
+        if let Some(recording) = &mut self.recording {
+            recording.finish(&status);
+        }
+        self.recording_error()
+    }
+
+    /// The error which stopped the recording, if any.
+    fn recording_error(&mut self) -> Result<(), crate::Error> {
+        match self.recording.as_mut().and_then(Recording::take_error) {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The output in `buffer` before the prompt and the prompt, once the last line
+/// matches `prompt`. Takes both from `buffer`.
+fn take_until_prompt(buffer: &mut Vec<u8>, prompt: &Regex) -> Option<(String, String)> {
+    let line_start = buffer
+        .iter()
+        .rposition(|&b| b == b'\n' || b == b'\r')
+        .map_or(0, |newline| newline + 1);
+    let last_line = clean(&buffer[line_start..]);
+    let matched = prompt.find_iter(&last_line).last()?;
+    if !last_line[matched.end()..].trim().is_empty() {
+        return None;
+    }
+    let output = clean(&buffer[..line_start]);
+    buffer.clear();
+    Some((output, last_line.trim().to_string()))
+}
+
+/// `bytes` as text without ANSI escape sequences and carriage returns.
+fn clean(bytes: &[u8]) -> String {
+    strip_ansi_escapes(&String::from_utf8_lossy(bytes)).replace('\r', "")
+}
+
+/// `output` without the first line if it is the echo of `command`.
+fn without_echo(output: String, command: &str) -> String {
+    let (first, rest) = output.split_once('\n').unwrap_or((&output, ""));
+    if !command.trim().is_empty() && first.trim_end().ends_with(command.trim()) {
+        rest.to_string()
+    } else {
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompts() {
+        let prompt = Regex::new(r"[\w.-]+(\(config[\w-]*\))?[>#]").unwrap();
+        let mut buffer = b"show clock\r\n*10:00:00.000 UTC Mon Mar 1 2024\r\n".to_vec();
+        assert_eq!(None, take_until_prompt(&mut buffer, &prompt));
+        buffer.extend_from_slice(b"router");
+        assert_eq!(None, take_until_prompt(&mut buffer, &prompt));
+        buffer.extend_from_slice(b"#");
+        assert_eq!(
+            Some((
+                "show clock\n*10:00:00.000 UTC Mon Mar 1 2024\n".to_string(),
+                "router#".to_string()
+            )),
+            take_until_prompt(&mut buffer, &prompt)
+        );
+        assert!(buffer.is_empty());
+
+        buffer.extend_from_slice(b"\x1b[0m\r\nrouter(config-if)# ");
+        assert_eq!(
+            Some(("\n".to_string(), "router(config-if)#".to_string())),
+            take_until_prompt(&mut buffer, &prompt)
+        );
+
+        // The prompt must end the output.
+        let mut buffer = b"\r\nrouter# show run".to_vec();
+        assert_eq!(None, take_until_prompt(&mut buffer, &prompt));
+    }
+
+    #[test]
+    fn echoes() {
+        assert_eq!(
+            "Cisco IOS\n",
+            without_echo("show version\nCisco IOS\n".into(), "show version")
+        );
+        assert_eq!(
+            "Cisco IOS\n",
+            without_echo("\x08\x08show version\nCisco IOS\n".into(), "show version ")
+        );
+        assert_eq!("other\n", without_echo("other\n".into(), "show version"));
+        assert_eq!("", without_echo(String::new(), "end"));
+        assert_eq!("\nfoo\n", without_echo("\nfoo\n".into(), ""));
+    }
+
+    #[test]
+    fn answers() {
+        assert!(matches!(
+            PromptAnswers::new([("(", "x")]),
+            Err(crate::Error::PromptPatternInvalid(_))
+        ));
+        let answers = PromptAnswers::new([
+            (r"^\[sudo\] password for \w+: *$", "secret"),
+            (r"\(yes/no(/\[fingerprint\])?\)\? *$", "yes"),
+            (r"\[y/N\] *$", "y"),
+            (r"\[Y/n\] *$", "n"),
+        ])
+        .unwrap();
+        let answer = |question| {
+            answers
+                .answer(question)
+                .map(|a| a.expose_secret().to_string())
+        };
+        assert_eq!(
+            Some("secret".into()),
+            answer("[sudo] password for deploy: ")
+        );
+        assert_eq!(
+            Some("yes".into()),
+            answer("Are you sure you want to continue connecting (yes/no/[fingerprint])? ")
+        );
+        assert_eq!(Some("y".into()), answer("Are you sure? [y/N] "));
+        assert_eq!(None, answer("Password:"));
+    }
+
+    #[test]
+    fn watcher() {
+        let answers = PromptAnswers::new([(r"\[y/N\] *$", "y")]).unwrap();
+        let mut watcher = PromptWatcher::new(Arc::new(answers));
+        assert_eq!(None, watcher.push(b"Removing 3 packages\r\nAre you"));
+        assert_eq!(
+            Some(b"y\n".to_vec()),
+            watcher
+                .push(b" sure? \x1b[1m[y/N]\x1b[0m ")
+                .map(|a| a.to_vec())
+        );
+        // Answered once, the echo starts over.
+        assert_eq!(None, watcher.push(b"y"));
+        assert_eq!(None, watcher.push(b"\r\nDone [y/N] in the log\r\n"));
+        assert_eq!(None, watcher.push(&[b'x'; 2 * MAX_QUESTION_LEN]));
+        assert_eq!(MAX_QUESTION_LEN, watcher.line.len());
+
+        let mut watcher = PromptWatcher::new(Arc::new(|question: &str| {
+            (question == "Continue? ").then(|| "yes".to_string())
+        }));
+        assert_eq!(
+            Some(b"yes\n".to_vec()),
+            watcher.push(b"Continue? ").map(|a| a.to_vec())
+        );
+    }
+}
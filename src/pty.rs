@@ -0,0 +1,265 @@
+//! Pseudo terminals for remote commands and shells.
+//!
+//! Programs like `sudo`, `top` or `systemctl` with its pager behave differently
+//! or refuse to run without a terminal. Request one with [`RemoteCommand::pty`]
+//! or [`Client::spawn_shell`], and keep its size in sync with the local
+//! terminal with [`RemoteChild::resize`].
+//!
+//! ```no_run
+//! use async_ssh2_tokio::{AuthMethod, Client, PtyRequest, RemoteCommand, ServerCheckMethod};
+//! #[tokio::main]
+//! async fn main() -> Result<(), async_ssh2_tokio::Error> {
+//!     let client = Client::connect(
+//!         ("10.10.10.2", 22),
+//!         "root",
+//!         AuthMethod::with_password("root"),
+//!         ServerCheckMethod::NoCheck,
+//!     ).await?;
+//!
+//!     let command = RemoteCommand::new("top")
+//!         .args(["-b", "-n", "1"])
+//!         .pty(PtyRequest::new("xterm-256color").size(160, 50));
+//!     let result = client.execute_command(&command).await?;
+//!     print!("{}", result.stdout);
+//!     Ok(())
+//! }
+//! ```
+//!
+//! [`RemoteCommand::pty`]: crate::RemoteCommand::pty
+//! [`Client::spawn_shell`]: crate::Client::spawn_shell
+//! [`RemoteChild::resize`]: crate::RemoteChild::resize
+
+use crate::client::{wait_request_reply, SessionChannel};
+use crate::record::Recording;
+use russh::client::Msg;
+use russh::{ChannelWriteHalf, Pty};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+/// A pseudo terminal to request for a remote command or shell.
+///
+/// With a terminal, the server merges stderr into stdout and usually
+/// translates `\n` to `\r\n`, and programs may print colors, ask for
+/// passwords and wait for keys. Its [modes](PtyRequest::mode) are left to the
+/// server unless set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PtyRequest {
+    term: String,
+    cols: u32,
+    rows: u32,
+    pixel_width: u32,
+    pixel_height: u32,
+    modes: Vec<(Pty, u32)>,
+}
+
+impl PtyRequest {
+    /// A terminal of type `term`, the `TERM` the remote programs see like
+    /// `"xterm-256color"`, with 80 columns and 24 rows.
+    pub fn new(term: impl Into<String>) -> Self {
+        Self {
+            term: term.into(),
+            cols: 80,
+            rows: 24,
+            pixel_width: 0,
+            pixel_height: 0,
+            modes: vec![],
+        }
+    }
+
+    /// Set the size in characters.
+    pub fn size(mut self, cols: u32, rows: u32) -> Self {
+        self.cols = cols;
+        self.rows = rows;
+        self
+    }
+
+    /// Set the size in pixels, 0 if unknown which is the default. Programs
+    /// go by the size in characters, the pixels only matter for graphics.
+    pub fn pixel_size(mut self, width: u32, height: u32) -> Self {
+        self.pixel_width = width;
+        self.pixel_height = height;
+        self
+    }
+
+    /// Set the POSIX terminal mode `mode` to `value`, like `stty` does, e.g.
+    /// `.mode(Pty::VINTR, 3)` for Ctrl-C interrupting. Flags like
+    /// [`Pty::ECHO`] are 1 to set and 0 to clear them, special characters like
+    /// [`Pty::VINTR`] are their code, 255 to disable them.
+    ///
+    /// ```
+    /// use async_ssh2_tokio::PtyRequest;
+    /// use russh::Pty;
+    ///
+    /// let pty = PtyRequest::new("vt100")
+    ///     .echo(false)
+    ///     .mode(Pty::VSUSP, 255)
+    ///     .mode(Pty::IUTF8, 1);
+    /// assert_eq!(pty.get_modes(), [(Pty::ECHO, 0), (Pty::VSUSP, 255), (Pty::IUTF8, 1)]);
+    /// ```
+    pub fn mode(mut self, mode: Pty, value: u32) -> Self {
+        match self.modes.iter_mut().find(|(set, _)| *set == mode) {
+            Some((_, set_value)) => *set_value = value,
+            None => self.modes.push((mode, value)),
+        }
+        self
+    }
+
+    /// Enable or disable echoing the input, e.g. disabled to script password
+    /// prompts without the password showing up in the output.
+    pub fn echo(self, enabled: bool) -> Self {
+        self.mode(Pty::ECHO, enabled.into())
+    }
+
+    /// Put the terminal in raw mode like `stty raw -echo`: input is passed on
+    /// byte by byte without echo, line editing or signal keys like Ctrl-C, and
+    /// output unchanged, without translating `\n` to `\r\n`.
+    pub fn raw(self) -> Self {
+        [
+            Pty::ICANON,
+            Pty::ISIG,
+            Pty::IEXTEN,
+            Pty::ECHO,
+            Pty::ICRNL,
+            Pty::IXON,
+            Pty::OPOST,
+        ]
+        .into_iter()
+        .fold(self, |pty, mode| pty.mode(mode, 0))
+    }
+
+    /// The type of the terminal.
+    pub fn get_term(&self) -> &str {
+        &self.term
+    }
+
+    /// The columns and rows of the terminal.
+    pub fn get_size(&self) -> (u32, u32) {
+        (self.cols, self.rows)
+    }
+
+    /// The width and height of the terminal in pixels.
+    pub fn get_pixel_size(&self) -> (u32, u32) {
+        (self.pixel_width, self.pixel_height)
+    }
+
+    /// The terminal modes set, in the order they were first set.
+    pub fn get_modes(&self) -> &[(Pty, u32)] {
+        &self.modes
+    }
+
+    /// Request this terminal on `channel`, before starting a command or shell
+    /// on it.
+    ///
+    /// Waits for the reply of the server, which is the next one on `channel`,
+    /// and fails with [`Error::PtyRequestRejected`](crate::Error::PtyRequestRejected)
+    /// if it refused, e.g. because of `PermitTTY no`.
+    pub async fn request(&self, channel: &mut SessionChannel) -> Result<(), crate::Error> {
+        channel
+            .request_pty(
+                true,
+                &self.term,
+                self.cols,
+                self.rows,
+                self.pixel_width,
+                self.pixel_height,
+                &self.modes,
+            )
+            .await?;
+        if !wait_request_reply(channel).await? {
+            return Err(crate::Error::PtyRequestRejected);
+        }
+        Ok(())
+    }
+}
+
+impl Hash for PtyRequest {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.term.hash(state);
+        self.get_size().hash(state);
+        self.get_pixel_size().hash(state);
+        for (mode, value) in &self.modes {
+            (*mode as u8, value).hash(state);
+        }
+    }
+}
+
+impl Default for PtyRequest {
+    /// An `xterm` of 80 columns and 24 rows.
+    fn default() -> Self {
+        Self::new("xterm")
+    }
+}
+
+/// Changes the size of the pseudo terminal of a [`RemoteChild`], see
+/// [`RemoteChild::resizer`].
+///
+/// [`RemoteChild`]: crate::RemoteChild
+/// [`RemoteChild::resizer`]: crate::RemoteChild::resizer
+#[derive(Clone)]
+pub struct PtyResizer {
+    channel: Arc<ChannelWriteHalf<Msg>>,
+    recording: Option<Arc<Mutex<Recording>>>, // of a recorded `RemoteChild`
+}
+
+impl PtyResizer {
+    pub(crate) fn new(
+        channel: Arc<ChannelWriteHalf<Msg>>,
+        recording: Option<Arc<Mutex<Recording>>>,
+    ) -> Self {
+        Self { channel, recording }
+    }
+
+    /// Tell the server the terminal is now `cols` columns wide and `rows` rows
+    /// high, with a `window-change` request. Programs like `less` or `vim` get
+    /// `SIGWINCH` and redraw. Does nothing for commands without a terminal.
+    pub async fn resize(&self, cols: u32, rows: u32) -> Result<(), crate::Error> {
+        self.resize_with_pixels(cols, rows, 0, 0).await
+    }
+
+    /// Like [`resize`](PtyResizer::resize), with the size in pixels as well.
+    pub async fn resize_with_pixels(
+        &self,
+        cols: u32,
+        rows: u32,
+        pixel_width: u32,
+        pixel_height: u32,
+    ) -> Result<(), crate::Error> {
+        self.channel
+            .window_change(cols, rows, pixel_width, pixel_height)
+            .await?;
+        if let Some(recording) = &self.recording {
+            recording.lock().unwrap().resize(cols, rows);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for PtyResizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PtyResizer")
+            .field("channel", &self.channel.id())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modes() {
+        let pty = PtyRequest::default();
+        assert_eq!(("xterm", (80, 24)), (pty.get_term(), pty.get_size()));
+        assert!(pty.get_modes().is_empty());
+
+        let pty = pty.echo(false).mode(Pty::VINTR, 255).echo(true);
+        assert_eq!([(Pty::ECHO, 1), (Pty::VINTR, 255)], pty.get_modes());
+
+        let pty = PtyRequest::new("vt100").mode(Pty::ONLCR, 1).raw();
+        assert!(pty.get_modes().contains(&(Pty::ICANON, 0)));
+        assert!(pty.get_modes().contains(&(Pty::ECHO, 0)));
+        assert_eq!((Pty::ONLCR, 1), pty.get_modes()[0]);
+        assert_eq!(8, pty.get_modes().len());
+    }
+}
@@ -0,0 +1,230 @@
+//! Quote arguments for the shells running remote commands.
+//!
+//! Command lines sent to a server are interpreted by a shell there, so any
+//! argument with spaces, quotes or characters like `$` and `;` must be quoted to
+//! be passed literally. [`RemoteCommand`] does this for you, these functions
+//! help with composing command lines by hand.
+//!
+//! ```
+//! use async_ssh2_tokio::quote;
+//!
+//! let file = "it's $HOME.txt";
+//! assert_eq!(format!("rm -- {}", quote::posix(file)), r"rm -- 'it'\''s $HOME.txt'");
+//! assert_eq!(quote::join_posix(["grep", "-r", "a b", "/var/log"]), "grep -r 'a b' /var/log");
+//! assert_eq!(quote::join_powershell(["Get-Item", file]), "& 'Get-Item' 'it''s $HOME.txt'");
+//! ```
+//!
+//! [`RemoteCommand`]: crate::RemoteCommand
+
+use std::borrow::Cow;
+
+/// Quote `word` for a POSIX shell like `sh`, `bash` or `zsh`, unless it
+/// consists of harmless characters.
+pub fn posix(word: &str) -> Cow<'_, str> {
+    let harmless = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !word.is_empty() && word.chars().all(harmless) {
+        Cow::Borrowed(word)
+    } else {
+        Cow::Owned(format!("'{}'", word.replace('\'', r"'\''")))
+    }
+}
+
+/// Quote `word` for fish, where backslashes escape in single quotes, unless it
+/// consists of harmless characters.
+pub fn fish(word: &str) -> Cow<'_, str> {
+    let harmless = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !word.is_empty() && word.chars().all(harmless) {
+        Cow::Borrowed(word)
+    } else {
+        let escaped = word.replace('\\', r"\\").replace('\'', r"\'");
+        Cow::Owned(format!("'{escaped}'"))
+    }
+}
+
+/// Quote `word` for csh and tcsh, where newlines must be escaped in quotes,
+/// unless it consists of harmless characters.
+pub fn csh(word: &str) -> Cow<'_, str> {
+    match posix(word) {
+        Cow::Owned(quoted) if quoted.contains('\n') => Cow::Owned(quoted.replace('\n', "\\\n")),
+        quoted => quoted,
+    }
+}
+
+/// Quote `word` for PowerShell, which takes typographic quotes like `'` as well.
+pub fn powershell(word: &str) -> Cow<'_, str> {
+    let mut quoted = String::with_capacity(word.len() + 2);
+    quoted.push('\'');
+    for c in word.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}') {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    Cow::Owned(quoted)
+}
+
+/// Quote `word` for the programs started by `cmd.exe`, which split their
+/// arguments like the Microsoft C runtime, unless it consists of harmless
+/// characters.
+///
+/// `%` can't be escaped for `cmd.exe`, so variables like `%PATH%` are expanded
+/// even in quoted words.
+pub fn cmd(word: &str) -> Cow<'_, str> {
+    let harmless = |c: char| c.is_ascii_alphanumeric() || "-_./\\:,+@=".contains(c);
+    if !word.is_empty() && word.chars().all(harmless) {
+        return Cow::Borrowed(word);
+    }
+    let mut quoted = String::with_capacity(word.len() + 2);
+    quoted.push('"');
+    let mut backslashes = 0;
+    for c in word.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            // Doubled, so `cmd.exe` stays in quoted mode.
+            '"' => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+                quoted.push_str("\"\"");
+                backslashes = 0;
+            }
+            c => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    // Backslashes before the closing quote would escape it.
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    Cow::Owned(quoted)
+}
+
+/// The command line running the program `argv[0]` with the arguments following
+/// it in a POSIX shell.
+pub fn join_posix<I>(argv: I) -> String
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let mut argv = argv.into_iter();
+    let Some(program) = argv.next() else {
+        return String::new();
+    };
+    let program = program.as_ref();
+    // A leading word like `FOO=bar` would be taken as a variable.
+    let mut line = if program.contains('=') {
+        format!("'{}'", program.replace('\'', r"'\''"))
+    } else {
+        posix(program).into_owned()
+    };
+    for arg in argv {
+        line.push(' ');
+        line += &posix(arg.as_ref());
+    }
+    line
+}
+
+/// The command line running the program `argv[0]` with the arguments following
+/// it in PowerShell, with the call operator `&` as the program is quoted.
+pub fn join_powershell<I>(argv: I) -> String
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let mut argv = argv.into_iter();
+    let Some(program) = argv.next() else {
+        return String::new();
+    };
+    let mut line = format!("& {}", powershell(program.as_ref()));
+    for arg in argv {
+        line.push(' ');
+        line += &powershell(arg.as_ref());
+    }
+    line
+}
+
+/// The command line running the program `argv[0]` with the arguments following
+/// it in `cmd.exe`.
+pub fn join_cmd<I>(argv: I) -> String
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    argv.into_iter()
+        .map(|word| cmd(word.as_ref()).into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn posix_quoting() {
+        assert_eq!("foo", posix("foo"));
+        assert_eq!("/tmp/a-b_c.txt", posix("/tmp/a-b_c.txt"));
+        assert_eq!("''", posix(""));
+        assert_eq!("'foo bar'", posix("foo bar"));
+        assert_eq!(r"'it'\''s'", posix("it's"));
+        assert_eq!("'$HOME `id` *; rm'", posix("$HOME `id` *; rm"));
+        assert_eq!("'a\nb'", posix("a\nb"));
+    }
+
+    #[test]
+    fn fish_quoting() {
+        assert_eq!("foo", fish("foo"));
+        assert_eq!("''", fish(""));
+        assert_eq!(r"'it\'s $HOME'", fish("it's $HOME"));
+        assert_eq!(r"'C:\\Temp\\'", fish(r"C:\Temp\"));
+    }
+
+    #[test]
+    fn csh_quoting() {
+        assert_eq!("foo", csh("foo"));
+        assert_eq!(r"'it'\''s $HOME'", csh("it's $HOME"));
+        assert_eq!("'a\\\nb'", csh("a\nb"));
+    }
+
+    #[test]
+    fn powershell_quoting() {
+        assert_eq!("'foo'", powershell("foo"));
+        assert_eq!("''", powershell(""));
+        assert_eq!("'it''s $HOME `n'", powershell("it's $HOME `n"));
+        assert_eq!("'it\u{2019}\u{2019}s'", powershell("it\u{2019}s"));
+    }
+
+    #[test]
+    fn cmd_quoting() {
+        assert_eq!(r"C:\Temp\a.txt", cmd(r"C:\Temp\a.txt"));
+        assert_eq!(r#""""#, cmd(""));
+        assert_eq!(r#""a & b | c""#, cmd("a & b | c"));
+        assert_eq!(r#""say ""hi""""#, cmd(r#"say "hi""#));
+        assert_eq!(r#""C:\My Dir\\""#, cmd(r"C:\My Dir\"));
+        assert_eq!(r#""a\\""b""#, cmd(r#"a\"b"#));
+    }
+
+    #[test]
+    fn joining() {
+        assert_eq!("", join_posix(Vec::<String>::new()));
+        assert_eq!("ls", join_posix(["ls"]));
+        assert_eq!("'./a=b' c=d", join_posix(["./a=b", "c=d"]));
+        assert_eq!(
+            r"echo 'it'\''s' '$HOME'",
+            join_posix(vec!["echo".to_string(), "it's".into(), "$HOME".into()])
+        );
+
+        assert_eq!("", join_powershell(Vec::<String>::new()));
+        assert_eq!(
+            r"& 'C:\Program Files\app.exe' '-v' 'it''s'",
+            join_powershell([r"C:\Program Files\app.exe", "-v", "it's"])
+        );
+
+        assert_eq!("", join_cmd(Vec::<String>::new()));
+        assert_eq!(
+            r#""C:\Program Files\app.exe" /v "it's""#,
+            join_cmd([r"C:\Program Files\app.exe", "/v", "it's"])
+        );
+    }
+}
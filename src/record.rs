@@ -0,0 +1,513 @@
+//! Record terminal sessions with their timing, for audit trails or to replay
+//! what an operator did, see [`SessionRecorder`].
+//!
+//! Sessions of [`LocalTerminal::run_recorded`], [`Client::spawn_recorded`],
+//! [`Client::spawn_shell_recorded`] and [`PromptSession::open_recorded`] are
+//! recorded. The `execute` methods and [`ShellSession`] aren't, their output is
+//! returned as a whole anyway.
+//!
+//! ```no_run
+//! use async_ssh2_tokio::record::Asciicast;
+//! use async_ssh2_tokio::{AuthMethod, Client, LocalTerminal, ServerCheckMethod};
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let client = Client::connect(
+//!         ("10.10.10.2", 22),
+//!         "root",
+//!         AuthMethod::with_password("root"),
+//!         ServerCheckMethod::NoCheck,
+//!     ).await?;
+//!
+//!     let file = std::fs::File::create("session.cast")?;
+//!     let mut recorder = Asciicast::new(std::io::BufWriter::new(file));
+//!     LocalTerminal::new().run_recorded(&client, &mut recorder).await?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! [`LocalTerminal::run_recorded`]: crate::LocalTerminal::run_recorded
+//! [`Client::spawn_recorded`]: crate::Client::spawn_recorded
+//! [`Client::spawn_shell_recorded`]: crate::Client::spawn_shell_recorded
+//! [`PromptSession::open_recorded`]: crate::PromptSession::open_recorded
+//! [`ShellSession`]: crate::ShellSession
+
+use crate::ExitStatus;
+use std::io::{self, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Receives what happens in a terminal session, e.g. from
+/// [`LocalTerminal::run_recorded`](crate::LocalTerminal::run_recorded) or
+/// [`Client::spawn_recorded`](crate::Client::spawn_recorded).
+///
+/// Times are counted from the start of the session. Implement it to send
+/// sessions elsewhere than the file formats [`Typescript`] and [`Asciicast`],
+/// e.g. to a log server.
+pub trait SessionRecorder: Send {
+    /// The session started in a terminal of type `term`, `cols` columns wide
+    /// and `rows` rows high.
+    fn start(&mut self, term: &str, cols: u32, rows: u32) -> io::Result<()>;
+
+    /// The remote side printed `data`.
+    fn output(&mut self, time: Duration, data: &[u8]) -> io::Result<()>;
+
+    /// The user typed `data`, which may include passwords. Ignored by default.
+    fn input(&mut self, time: Duration, data: &[u8]) -> io::Result<()> {
+        let _ = (time, data);
+        Ok(())
+    }
+
+    /// The terminal was resized. Ignored by default.
+    fn resize(&mut self, time: Duration, cols: u32, rows: u32) -> io::Result<()> {
+        let _ = (time, cols, rows);
+        Ok(())
+    }
+
+    /// The session ended with `status`.
+    fn finish(&mut self, time: Duration, status: &ExitStatus) -> io::Result<()>;
+}
+
+/// A started recording of a session, timed from its start.
+///
+/// The first error of the recorder stops the recording and is kept until it is
+/// [taken](Recording::take_error). Nothing is recorded after the finish.
+pub(crate) struct Recording {
+    recorder: Box<dyn SessionRecorder>,
+    started: Instant,
+    finished: bool,
+    error: Option<io::Error>,
+}
+
+impl Recording {
+    /// Start recording with `recorder` a session in a terminal of type `term`.
+    pub(crate) fn start(
+        mut recorder: Box<dyn SessionRecorder>,
+        term: &str,
+        cols: u32,
+        rows: u32,
+    ) -> Result<Self, crate::Error> {
+        recorder
+            .start(term, cols, rows)
+            .map_err(crate::Error::RecordingFailed)?;
+        Ok(Self {
+            recorder,
+            started: Instant::now(),
+            finished: false,
+            error: None,
+        })
+    }
+
+    pub(crate) fn output(&mut self, data: &[u8]) {
+        self.record(|recorder, time| recorder.output(time, data));
+    }
+
+    pub(crate) fn input(&mut self, data: &[u8]) {
+        self.record(|recorder, time| recorder.input(time, data));
+    }
+
+    pub(crate) fn resize(&mut self, cols: u32, rows: u32) {
+        self.record(|recorder, time| recorder.resize(time, cols, rows));
+    }
+
+    /// Finish the recording, unless it already was.
+    pub(crate) fn finish(&mut self, status: &ExitStatus) {
+        self.record(|recorder, time| recorder.finish(time, status));
+        self.finished = true;
+    }
+
+    /// The error which stopped the recording, as
+    /// [`Error::RecordingFailed`](crate::Error::RecordingFailed).
+    pub(crate) fn take_error(&mut self) -> Option<crate::Error> {
+        self.error.take().map(crate::Error::RecordingFailed)
+    }
+
+    fn record(&mut self, event: impl FnOnce(&mut dyn SessionRecorder, Duration) -> io::Result<()>) {
+        if self.finished || self.error.is_some() {
+            return;
+        }
+        if let Err(e) = event(self.recorder.as_mut(), self.started.elapsed()) {
+            self.error = Some(e);
+        }
+    }
+}
+
+impl std::fmt::Debug for Recording {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Recording")
+            .field("finished", &self.finished)
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Records the output like `script`, to a typescript file which can be shown
+/// with `cat`, and optionally the timing to replay it with `scriptreplay`.
+#[derive(Debug)]
+pub struct Typescript<W, T = io::Sink> {
+    typescript: W,
+    timing: Option<T>,
+    last_output: Duration,
+}
+
+impl<W: Write> Typescript<W> {
+    /// Write the typescript to `typescript`, without timing.
+    pub fn new(typescript: W) -> Self {
+        Self {
+            typescript,
+            timing: None,
+            last_output: Duration::ZERO,
+        }
+    }
+}
+
+impl<W: Write, T: Write> Typescript<W, T> {
+    /// Write the typescript to `typescript` and the timing to `timing`, in the
+    /// classic format of `script --log-timing` which `scriptreplay` reads.
+    pub fn with_timing(typescript: W, timing: T) -> Self {
+        Self {
+            typescript,
+            timing: Some(timing),
+            last_output: Duration::ZERO,
+        }
+    }
+
+    /// The writers of the typescript and the timing.
+    pub fn into_inner(self) -> (W, Option<T>) {
+        (self.typescript, self.timing)
+    }
+}
+
+impl<W: Write + Send, T: Write + Send> SessionRecorder for Typescript<W, T> {
+    fn start(&mut self, term: &str, cols: u32, rows: u32) -> io::Result<()> {
+        writeln!(
+            self.typescript,
+            "Script started on {} [TERM=\"{term}\" COLUMNS=\"{cols}\" LINES=\"{rows}\"]",
+            utc_date(SystemTime::now())
+        )
+    }
+
+    fn output(&mut self, time: Duration, data: &[u8]) -> io::Result<()> {
+        self.typescript.write_all(data)?;
+        if let Some(timing) = &mut self.timing {
+            let delay = time.saturating_sub(self.last_output);
+            writeln!(timing, "{:.6} {}", delay.as_secs_f64(), data.len())?;
+        }
+        self.last_output = time;
+        Ok(())
+    }
+
+    fn finish(&mut self, _time: Duration, status: &ExitStatus) -> io::Result<()> {
+        writeln!(
+            self.typescript,
+            "\nScript done on {} [COMMAND_EXIT_CODE=\"{}\"]",
+            utc_date(SystemTime::now()),
+            status.shell_status()
+        )?;
+        self.typescript.flush()?;
+        if let Some(timing) = &mut self.timing {
+            timing.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Records the session in the asciicast v2 format of asciinema, to play it
+/// with `asciinema play` or in a browser.
+#[derive(Debug)]
+pub struct Asciicast<W> {
+    writer: W,
+    record_input: bool,
+    output: Utf8Chunks,
+    input: Utf8Chunks,
+}
+
+impl<W: Write> Asciicast<W> {
+    /// Write the recording to `writer`, without the input.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            record_input: false,
+            output: Utf8Chunks::default(),
+            input: Utf8Chunks::default(),
+        }
+    }
+
+    /// Record the input as well, including passwords typed at prompts which
+    /// don't echo them.
+    pub fn record_input(mut self, enabled: bool) -> Self {
+        self.record_input = enabled;
+        self
+    }
+
+    /// The writer of the recording.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn event(&mut self, time: Duration, code: &str, data: &str) -> io::Result<()> {
+        writeln!(
+            self.writer,
+            "[{:.6}, \"{code}\", {}]",
+            time.as_secs_f64(),
+            json_string(data)
+        )
+    }
+}
+
+impl<W: Write + Send> SessionRecorder for Asciicast<W> {
+    fn start(&mut self, term: &str, cols: u32, rows: u32) -> io::Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        writeln!(
+            self.writer,
+            "{{\"version\": 2, \"width\": {cols}, \"height\": {rows}, \"timestamp\": {timestamp}, \
+             \"env\": {{\"TERM\": {}}}}}",
+            json_string(term)
+        )
+    }
+
+    fn output(&mut self, time: Duration, data: &[u8]) -> io::Result<()> {
+        let text = self.output.push(data);
+        if text.is_empty() {
+            return Ok(());
+        }
+        self.event(time, "o", &text)
+    }
+
+    fn input(&mut self, time: Duration, data: &[u8]) -> io::Result<()> {
+        if !self.record_input {
+            return Ok(());
+        }
+        let text = self.input.push(data);
+        if text.is_empty() {
+            return Ok(());
+        }
+        self.event(time, "i", &text)
+    }
+
+    fn resize(&mut self, time: Duration, cols: u32, rows: u32) -> io::Result<()> {
+        self.event(time, "r", &format!("{cols}x{rows}"))
+    }
+
+    fn finish(&mut self, _time: Duration, _status: &ExitStatus) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Decodes UTF-8 arriving in chunks, which may split characters.
+#[derive(Debug, Default)]
+struct Utf8Chunks {
+    incomplete: Vec<u8>, // the start of a character split by the last chunk
+}
+
+impl Utf8Chunks {
+    fn push(&mut self, data: &[u8]) -> String {
+        let mut bytes = std::mem::take(&mut self.incomplete);
+        bytes.extend_from_slice(data);
+        if let Err(e) = std::str::from_utf8(&bytes) {
+            if e.error_len().is_none() {
+                self.incomplete = bytes.split_off(e.valid_up_to());
+            }
+        }
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+/// `text` as JSON string, with quotes.
+fn json_string(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' || c == '\u{7f}' => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// `time` like `2024-05-01 10:00:00+00:00`.
+fn utc_date(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, seconds) = (seconds / 86400, seconds % 86400);
+    // The civil date of a day count, by Howard Hinnant's algorithm.
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}+00:00",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typescript() {
+        let mut typescript = Typescript::with_timing(Vec::new(), Vec::new());
+        typescript.start("xterm", 80, 24).unwrap();
+        typescript
+            .output(Duration::from_millis(100), b"$ ")
+            .unwrap();
+        typescript
+            .input(Duration::from_millis(900), b"ls\r")
+            .unwrap();
+        typescript
+            .output(Duration::from_millis(1250), b"ls\r\nfile\r\n")
+            .unwrap();
+        typescript
+            .finish(Duration::from_secs(2), &ExitStatus::Exited(3))
+            .unwrap();
+
+        let (typescript, timing) = typescript.into_inner();
+        let typescript = String::from_utf8(typescript).unwrap();
+        let (header, rest) = typescript.split_once('\n').unwrap();
+        assert!(header.starts_with("Script started on "), "{header}");
+        assert!(header.ends_with(" [TERM=\"xterm\" COLUMNS=\"80\" LINES=\"24\"]"));
+        let (output, footer) = rest.split_once("\nScript done on ").unwrap();
+        assert_eq!("$ ls\r\nfile\r\n", output);
+        assert!(footer.ends_with(" [COMMAND_EXIT_CODE=\"3\"]\n"), "{footer}");
+        assert_eq!(
+            "0.100000 2\n1.150000 10\n",
+            String::from_utf8(timing.unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn asciicast() {
+        let mut cast = Asciicast::new(Vec::new()).record_input(true);
+        cast.start("xterm-256color", 120, 40).unwrap();
+        cast.output(Duration::from_millis(500), b"caf\xc3").unwrap();
+        cast.output(Duration::from_millis(750), b"\xa9 \"ok\"\r\n\x1b[0m")
+            .unwrap();
+        cast.input(Duration::from_secs(1), b"exit\r").unwrap();
+        cast.resize(Duration::from_secs(2), 100, 30).unwrap();
+        cast.finish(Duration::from_secs(3), &ExitStatus::Exited(0))
+            .unwrap();
+
+        let cast = String::from_utf8(cast.into_inner()).unwrap();
+        let lines: Vec<_> = cast.lines().collect();
+        assert!(
+            lines[0].starts_with(r#"{"version": 2, "width": 120, "height": 40, "timestamp": "#),
+            "{}",
+            lines[0]
+        );
+        assert!(lines[0].ends_with(r#", "env": {"TERM": "xterm-256color"}}"#));
+        assert_eq!(
+            [
+                r#"[0.500000, "o", "caf"]"#,
+                r#"[0.750000, "o", "é \"ok\"\r\n\u001b[0m"]"#,
+                r#"[1.000000, "i", "exit\r"]"#,
+                r#"[2.000000, "r", "100x30"]"#,
+            ],
+            lines[1..]
+        );
+
+        let mut cast = Asciicast::new(Vec::new());
+        cast.input(Duration::ZERO, b"secret\r").unwrap();
+        assert!(cast.into_inner().is_empty());
+    }
+
+    #[test]
+    fn recording() {
+        /// Fails on the second output, to check that the recording stops.
+        struct Failing(std::sync::Arc<std::sync::Mutex<Vec<String>>>);
+
+        impl SessionRecorder for Failing {
+            fn start(&mut self, term: &str, _cols: u32, _rows: u32) -> io::Result<()> {
+                self.0.lock().unwrap().push(format!("start {term}"));
+                Ok(())
+            }
+
+            fn output(&mut self, _time: Duration, data: &[u8]) -> io::Result<()> {
+                let mut events = self.0.lock().unwrap();
+                if events.len() > 1 {
+                    return Err(io::ErrorKind::StorageFull.into());
+                }
+                events.push(String::from_utf8_lossy(data).into_owned());
+                Ok(())
+            }
+
+            fn finish(&mut self, _time: Duration, status: &ExitStatus) -> io::Result<()> {
+                self.0.lock().unwrap().push(format!("finish {status}"));
+                Ok(())
+            }
+        }
+
+        let events = std::sync::Arc::default();
+        let mut recording = Recording::start(
+            Box::new(Failing(std::sync::Arc::clone(&events))),
+            "vt100",
+            80,
+            24,
+        )
+        .unwrap();
+        recording.output(b"first");
+        recording.finish(&ExitStatus::Exited(0));
+        recording.output(b"after");
+        assert!(recording.take_error().is_none());
+        assert_eq!(
+            ["start vt100", "first", "finish exit code 0"],
+            events.lock().unwrap()[..]
+        );
+
+        let events = std::sync::Arc::default();
+        let mut recording = Recording::start(
+            Box::new(Failing(std::sync::Arc::clone(&events))),
+            "vt100",
+            80,
+            24,
+        )
+        .unwrap();
+        recording.output(b"first");
+        recording.output(b"second");
+        recording.finish(&ExitStatus::Exited(0));
+        assert!(matches!(
+            recording.take_error(),
+            Some(crate::Error::RecordingFailed(e)) if e.kind() == io::ErrorKind::StorageFull
+        ));
+        assert_eq!(["start vt100", "first"], events.lock().unwrap()[..]);
+    }
+
+    #[test]
+    fn utf8_chunks() {
+        let mut chunks = Utf8Chunks::default();
+        assert_eq!("", chunks.push(b"\xe2\x82"));
+        assert_eq!("€a", chunks.push(b"\xaca"));
+        assert_eq!("\u{fffd}b", chunks.push(b"\xffb"));
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!("1970-01-01 00:00:00+00:00", utc_date(UNIX_EPOCH));
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        assert_eq!("2024-02-29 12:34:56+00:00", utc_date(time));
+        let time = UNIX_EPOCH + Duration::from_secs(951_782_400);
+        assert_eq!("2000-02-29 00:00:00+00:00", utc_date(time));
+    }
+}
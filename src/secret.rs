@@ -0,0 +1,92 @@
+//! Passwords, passphrases and private keys held by an [`AuthMethod`].
+//!
+//! [`AuthMethod`]: crate::AuthMethod
+
+use secrecy::{ExposeSecret, SecretBox};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use zeroize::Zeroize;
+
+/// A secret which is zeroized when dropped and redacted in `Debug` output.
+///
+/// Clones are separate copies, each scrubbed on its own drop. The [`AuthMethod`]
+/// passed to [`Client::connect`] is dropped, and so scrubbed, once the
+/// authentification finished. Note that russh keeps its own copy of a password
+/// for the duration of the authentification request.
+///
+/// [`AuthMethod`]: crate::AuthMethod
+/// [`Client::connect`]: crate::Client::connect
+pub struct Secret<S: Zeroize + ?Sized>(SecretBox<S>);
+
+impl<S: Zeroize + ?Sized> Secret<S> {
+    /// Gives access to the secret, make sure not to copy it around.
+    pub fn expose_secret(&self) -> &S {
+        self.0.expose_secret()
+    }
+}
+
+impl From<String> for Secret<str> {
+    fn from(secret: String) -> Self {
+        Self(secret.into())
+    }
+}
+
+impl From<&str> for Secret<str> {
+    fn from(secret: &str) -> Self {
+        Self(secret.into())
+    }
+}
+
+impl From<Vec<u8>> for Secret<[u8]> {
+    fn from(secret: Vec<u8>) -> Self {
+        Self(secret.into())
+    }
+}
+
+impl From<&[u8]> for Secret<[u8]> {
+    fn from(secret: &[u8]) -> Self {
+        Self(secret.to_vec().into())
+    }
+}
+
+impl<S: Zeroize + ?Sized> Clone for Secret<S>
+where
+    SecretBox<S>: Clone,
+{
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<S: Zeroize + ?Sized> fmt::Debug for Secret<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret([REDACTED])")
+    }
+}
+
+impl<S: Zeroize + PartialEq + ?Sized> PartialEq for Secret<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.expose_secret() == other.expose_secret()
+    }
+}
+
+impl<S: Zeroize + Eq + ?Sized> Eq for Secret<S> {}
+
+impl<S: Zeroize + Hash + ?Sized> Hash for Secret<S> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.expose_secret().hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacted_debug() {
+        let secret = Secret::<str>::from("hunter2");
+        assert_eq!("Secret([REDACTED])", format!("{secret:?}"));
+        assert_eq!("hunter2", secret.clone().expose_secret());
+        assert_eq!(secret, Secret::from("hunter2".to_string()));
+    }
+}
@@ -0,0 +1,235 @@
+//! Authentification with FIDO2 security keys (`sk-ssh-ed25519@openssh.com` and
+//! `sk-ecdsa-sha2-nistp256@openssh.com`).
+//!
+//! The private key file of a security key only holds a key handle, the signing
+//! happens on the device. This crate does not talk to the device itself, the
+//! [`SecurityKeyHandler`] does, e.g. through `libfido2` or `ctap-hid-fido2`.
+//! Resident keys can be used after exporting their key handle files with
+//! `ssh-keygen -K`, or through an ssh-agent with [`AuthMethod::Agent`].
+//!
+//! [`AuthMethod::Agent`]: crate::AuthMethod::Agent
+
+use crate::client::BoxFuture;
+use crate::wire::put_string;
+use russh::keys::agent::AgentIdentity;
+use russh::keys::ssh_key::private::KeypairData;
+use russh::keys::{HashAlg, PrivateKey, PublicKey};
+use russh::{SendError, Signer};
+use std::future::Future;
+use std::sync::Arc;
+
+/// The key requires the user to touch the device.
+const FLAG_USER_PRESENCE: u8 = 0x01;
+/// The key requires the user to verify, e.g. with a PIN or fingerprint.
+const FLAG_USER_VERIFICATION: u8 = 0x04;
+
+/// An assertion the [`SecurityKeyHandler`] has to request from the device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecurityKeyRequest {
+    /// The FIDO relying party id of the credential, usually `ssh:`.
+    pub application: String,
+    /// The FIDO credential id, stored in the private key file.
+    pub key_handle: Vec<u8>,
+    /// The client data hash to sign, the SHA256 digest of the ssh session data.
+    pub challenge: Vec<u8>,
+    /// Whether the user has to touch the device, tell them to do so.
+    pub user_presence_required: bool,
+    /// Whether the user has to verify, e.g. by entering the PIN of the device.
+    pub user_verification_required: bool,
+}
+
+/// The assertion of the device for a [`SecurityKeyRequest`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecurityKeySignature {
+    /// Flags of the authenticator data, e.g. if the user was present.
+    pub flags: u8,
+    /// Signature counter of the authenticator data.
+    pub counter: u32,
+    /// The raw signature as returned by the device: 64 bytes for Ed25519,
+    /// ASN.1 DER for ECDSA.
+    pub signature: Vec<u8>,
+}
+
+type SecurityKeyFn =
+    dyn Fn(SecurityKeyRequest) -> BoxFuture<Option<SecurityKeySignature>> + Send + Sync;
+
+/// The user supplied access to the security key of an [`AuthMethod::SecurityKeyFile`].
+///
+/// [`AuthMethod::SecurityKeyFile`]: crate::AuthMethod::SecurityKeyFile
+#[derive(Clone)]
+pub struct SecurityKeyHandler(Arc<SecurityKeyFn>);
+
+impl SecurityKeyHandler {
+    /// Wraps an async function requesting an assertion from the device.
+    ///
+    /// Returning `None`, e.g. if the user didn't touch the key in time, aborts
+    /// the authentification.
+    pub fn new<F, Fut>(handler: F) -> Self
+    where
+        F: Fn(SecurityKeyRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<SecurityKeySignature>> + Send + 'static,
+    {
+        Self(Arc::new(move |request| Box::pin(handler(request))))
+    }
+}
+
+handler_identity!(SecurityKeyHandler);
+
+#[derive(Debug)]
+pub(crate) struct SecurityKeyError;
+
+impl From<SendError> for SecurityKeyError {
+    fn from(_: SendError) -> Self {
+        Self
+    }
+}
+
+/// Signs the authentification request of a `sk-*` key through a [`SecurityKeyHandler`].
+pub(crate) struct SecurityKeySigner {
+    handler: SecurityKeyHandler,
+    public_key: PublicKey,
+    application: String,
+    key_handle: Vec<u8>,
+    flags: u8,
+}
+
+impl SecurityKeySigner {
+    /// Returns `None` if `key` is not a security key.
+    pub(crate) fn new(key: &PrivateKey, handler: SecurityKeyHandler) -> Option<Self> {
+        let (application, key_handle, flags) = match key.key_data() {
+            KeypairData::SkEd25519(sk) => (sk.public().application(), sk.key_handle(), sk.flags()),
+            KeypairData::SkEcdsaSha2NistP256(sk) => {
+                (sk.public().application(), sk.key_handle(), sk.flags())
+            }
+            _ => return None,
+        };
+        Some(Self {
+            handler,
+            public_key: key.public_key().clone(),
+            application: application.to_string(),
+            key_handle: key_handle.to_vec(),
+            flags,
+        })
+    }
+
+    pub(crate) fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+}
+
+impl Signer for SecurityKeySigner {
+    type Error = SecurityKeyError;
+
+    async fn auth_sign(
+        &mut self,
+        _key: &AgentIdentity,
+        _hash_alg: Option<HashAlg>,
+        mut to_sign: Vec<u8>,
+    ) -> Result<Vec<u8>, Self::Error> {
+        let request = SecurityKeyRequest {
+            application: self.application.clone(),
+            key_handle: self.key_handle.clone(),
+            challenge: HashAlg::Sha256.digest(&to_sign),
+            user_presence_required: self.flags & FLAG_USER_PRESENCE != 0,
+            user_verification_required: self.flags & FLAG_USER_VERIFICATION != 0,
+        };
+        let signature = (self.handler.0)(request).await.ok_or(SecurityKeyError)?;
+        let blob = encode_signature(self.public_key.algorithm().as_str(), &signature)
+            .ok_or(SecurityKeyError)?;
+        put_string(&mut to_sign, &blob);
+        Ok(to_sign)
+    }
+}
+
+/// Encodes the assertion of the device as ssh signature, see `PROTOCOL.u2f` of OpenSSH.
+fn encode_signature(algorithm: &str, signature: &SecurityKeySignature) -> Option<Vec<u8>> {
+    let mut blob = Vec::new();
+    put_string(&mut blob, algorithm.as_bytes());
+    if algorithm.starts_with("sk-ecdsa-") {
+        let (r, s) = parse_der_signature(&signature.signature)?;
+        let mut ecdsa = Vec::new();
+        put_string(&mut ecdsa, r);
+        put_string(&mut ecdsa, s);
+        put_string(&mut blob, &ecdsa);
+    } else {
+        put_string(&mut blob, &signature.signature);
+    }
+    blob.push(signature.flags);
+    blob.extend_from_slice(&signature.counter.to_be_bytes());
+    Some(blob)
+}
+
+/// Splits an ASN.1 DER `SEQUENCE { INTEGER r, INTEGER s }` into `r` and `s`.
+///
+/// DER integers are minimal big-endian two's complement, just like ssh `mpint`s.
+fn parse_der_signature(der: &[u8]) -> Option<(&[u8], &[u8])> {
+    fn element(data: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+        let (&actual, data) = data.split_first()?;
+        let (&len, data) = data.split_first()?;
+        let (len, data) = match len {
+            0x00..=0x7f => (len as usize, data),
+            0x81 => {
+                let (&len, data) = data.split_first()?;
+                (len as usize, data)
+            }
+            _ => return None,
+        };
+        (actual == tag && data.len() >= len).then(|| data.split_at(len))
+    }
+
+    let (sequence, rest) = element(der, 0x30)?;
+    if !rest.is_empty() {
+        return None;
+    }
+    let (r, sequence) = element(sequence, 0x02)?;
+    let (s, sequence) = element(sequence, 0x02)?;
+    sequence.is_empty().then_some((r, s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn der_signature() {
+        let der = [0x30, 0x08, 0x02, 0x02, 0x00, 0x80, 0x02, 0x02, 0x12, 0x34];
+        assert_eq!(
+            Some((&[0x00, 0x80][..], &[0x12, 0x34][..])),
+            parse_der_signature(&der)
+        );
+        assert_eq!(None, parse_der_signature(&der[..9]));
+        assert_eq!(None, parse_der_signature(&[0x31, 0x00]));
+    }
+
+    #[test]
+    fn ed25519_signature_blob() {
+        let signature = SecurityKeySignature {
+            flags: 0x01,
+            counter: 7,
+            signature: vec![0xab; 64],
+        };
+        let blob = encode_signature("sk-ssh-ed25519@openssh.com", &signature).unwrap();
+
+        let mut expected = Vec::new();
+        put_string(&mut expected, b"sk-ssh-ed25519@openssh.com");
+        put_string(&mut expected, &[0xab; 64]);
+        expected.extend_from_slice(&[0x01, 0, 0, 0, 7]);
+        assert_eq!(expected, blob);
+    }
+
+    #[test]
+    fn ecdsa_signature_blob() {
+        let signature = SecurityKeySignature {
+            flags: 0x05,
+            counter: 1,
+            signature: vec![0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02],
+        };
+        let blob = encode_signature("sk-ecdsa-sha2-nistp256@openssh.com", &signature).unwrap();
+
+        let mut expected = Vec::new();
+        put_string(&mut expected, b"sk-ecdsa-sha2-nistp256@openssh.com");
+        put_string(&mut expected, &[0, 0, 0, 1, 0x01, 0, 0, 0, 1, 0x02]);
+        expected.extend_from_slice(&[0x05, 0, 0, 0, 1]);
+        assert_eq!(expected, blob);
+    }
+}
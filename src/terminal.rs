@@ -0,0 +1,335 @@
+//! Bridge the local terminal to a remote shell, like `ssh` does without a
+//! command, see [`LocalTerminal`]. Only available on Unix.
+
+use crate::record::SessionRecorder;
+use crate::{Client, ExitStatus, PtyRequest, RemoteCommand};
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::fs::OpenOptionsExt;
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Time between checks whether the local terminal was resized.
+const RESIZE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Runs a remote shell, or command, in a pseudo terminal connected to the local
+/// one, e.g. to offer an "open a shell" action in an application.
+///
+/// The local terminal is put in raw mode, so keys like Ctrl-C, Ctrl-Z or the
+/// arrows reach the remote programs, and is restored when the shell exits. The
+/// remote terminal gets the `TERM` and size of the local one, and follows when
+/// it is resized. Like with `ssh`, typing the escape character `~` followed by
+/// `.` at the start of a line disconnects, and `~~` sends a single `~`.
+///
+/// Needs a controlling terminal, whose input is read through `/dev/tty`, and
+/// stdout to be a terminal, otherwise [`run`](LocalTerminal::run) fails with
+/// [`Error::TerminalFailed`](crate::Error::TerminalFailed).
+///
+/// # Examples
+///
+/// ```no_run
+/// use async_ssh2_tokio::{AuthMethod, Client, LocalTerminal, ServerCheckMethod};
+/// #[tokio::main]
+/// async fn main() -> Result<(), async_ssh2_tokio::Error> {
+///     let client = Client::connect(
+///         ("10.10.10.2", 22),
+///         "root",
+///         AuthMethod::with_password("root"),
+///         ServerCheckMethod::NoCheck,
+///     ).await?;
+///
+///     let status = LocalTerminal::new().run(&client).await?;
+///     println!("the shell ended with {status}");
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LocalTerminal {
+    command: Option<RemoteCommand>,
+    escape_char: Option<u8>,
+}
+
+impl LocalTerminal {
+    /// Run the login shell of the remote user, with `~` as escape character.
+    pub fn new() -> Self {
+        Self {
+            command: None,
+            escape_char: Some(b'~'),
+        }
+    }
+
+    /// Run `command` instead of the login shell, like `ssh -t host command`.
+    pub fn command(mut self, command: RemoteCommand) -> Self {
+        self.command = Some(command);
+        self
+    }
+
+    /// Set the escape character, an ASCII character, or disable it with `None`
+    /// to pass all input on.
+    pub fn escape_char(mut self, escape_char: Option<char>) -> Self {
+        self.escape_char = escape_char.filter(char::is_ascii).map(|c| c as u8);
+        self
+    }
+
+    /// Run the shell on the host of `client` until it exits, and return how it
+    /// ended. [`ExitStatus::Unknown`] if it was disconnected with the escape
+    /// character.
+    ///
+    /// Reading the local input doesn't take a thread, it is read from `/dev/tty`
+    /// opened separately in non-blocking mode, which leaves the mode of stdin
+    /// and stdout as it is. The terminal settings are restored afterwards, so
+    /// the application can go on using stdin.
+    pub async fn run(&self, client: &Client) -> Result<ExitStatus, crate::Error> {
+        self.bridge(client, None).await
+    }
+
+    /// Like [`run`](LocalTerminal::run), recording the session with `recorder`,
+    /// e.g. an [`Asciicast`](crate::record::Asciicast) file.
+    pub async fn run_recorded(
+        &self,
+        client: &Client,
+        recorder: &mut dyn SessionRecorder,
+    ) -> Result<ExitStatus, crate::Error> {
+        self.bridge(client, Some(recorder)).await
+    }
+
+    async fn bridge(
+        &self,
+        client: &Client,
+        mut recorder: Option<&mut dyn SessionRecorder>,
+    ) -> Result<ExitStatus, crate::Error> {
+        let mut size = terminal_size().map_err(crate::Error::TerminalFailed)?;
+        let term = std::env::var("TERM").unwrap_or_else(|_| "xterm".to_string());
+        let pty = PtyRequest::new(&term).size(size.0, size.1);
+        let mut child = match &self.command {
+            Some(command) => client.spawn(&command.clone().pty(pty)).await?,
+            None => client.spawn_shell(Some(&pty)).await?,
+        };
+        let (input, raw_mode) = match open_tty() {
+            Ok(tty) => tty,
+            Err(e) => {
+                // Nobody would see or end the remote shell.
+                let _ = child.kill().await;
+                return Err(crate::Error::TerminalFailed(e));
+            }
+        };
+        let started = std::time::Instant::now();
+        if let Some(recorder) = recorder.as_deref_mut() {
+            recorder
+                .start(&term, size.0, size.1)
+                .map_err(crate::Error::RecordingFailed)?;
+        }
+
+        let mut remote_input = child.stdin().expect("no stdin data was sent");
+        let mut remote_output = child.stdout().expect("stdout wasn't taken");
+        let resizer = child.resizer();
+        let mut output = tokio::io::stdout();
+        let mut escape = EscapeFilter::new(self.escape_char);
+        let mut resize_check = tokio::time::interval(RESIZE_POLL_INTERVAL);
+        let (mut input_buf, mut output_buf) = ([0; 4096], [0; 4096]);
+        let mut input_open = true;
+        let mut disconnected = false;
+        loop {
+            tokio::select! {
+                read = remote_output.read(&mut output_buf) => {
+                    let read = read.map_err(crate::Error::TerminalFailed)?;
+                    if read == 0 {
+                        break;
+                    }
+                    if let Some(recorder) = recorder.as_deref_mut() {
+                        recorder
+                            .output(started.elapsed(), &output_buf[..read])
+                            .map_err(crate::Error::RecordingFailed)?;
+                    }
+                    output
+                        .write_all(&output_buf[..read])
+                        .await
+                        .map_err(crate::Error::TerminalFailed)?;
+                    output.flush().await.map_err(crate::Error::TerminalFailed)?;
+                }
+                ready = input.readable(), if input_open => {
+                    let mut ready = ready.map_err(crate::Error::TerminalFailed)?;
+                    let Ok(read) = ready.try_io(|tty| tty.get_ref().read(&mut input_buf)) else {
+                        continue;
+                    };
+                    let read = read.map_err(crate::Error::TerminalFailed)?;
+                    if read == 0 {
+                        input_open = false;
+                        let _ = remote_input.shutdown().await;
+                        continue;
+                    }
+                    let (data, disconnect) = escape.push(&input_buf[..read]);
+                    if let Some(recorder) = recorder.as_deref_mut() {
+                        recorder
+                            .input(started.elapsed(), &data)
+                            .map_err(crate::Error::RecordingFailed)?;
+                    }
+                    // Fails only once the channel closed, the output loop ends then.
+                    let _ = remote_input.write_all(&data).await;
+                    if disconnect {
+                        child.kill().await?;
+                        disconnected = true;
+                        break;
+                    }
+                }
+                _ = resize_check.tick() => {
+                    if let Ok(new_size) = terminal_size() {
+                        if new_size != size {
+                            size = new_size;
+                            resizer.resize(size.0, size.1).await?;
+                            if let Some(recorder) = recorder.as_deref_mut() {
+                                recorder
+                                    .resize(started.elapsed(), size.0, size.1)
+                                    .map_err(crate::Error::RecordingFailed)?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        drop(remote_input);
+        drop(raw_mode);
+        let status = match child.wait().await {
+            _ if disconnected => ExitStatus::Unknown,
+            Err(crate::Error::CommandDidntExit) => ExitStatus::Unknown,
+            status => status?,
+        };
+        if let Some(recorder) = recorder {
+            recorder
+                .finish(started.elapsed(), &status)
+                .map_err(crate::Error::RecordingFailed)?;
+        }
+        Ok(status)
+    }
+}
+
+impl Default for LocalTerminal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The controlling terminal, opened for non-blocking reads, and put in raw
+/// mode.
+///
+/// A file of its own, as `O_NONBLOCK` on stdin would apply to the open file
+/// description it usually shares with stdout and stderr, failing their writes
+/// with `WouldBlock`. Without `O_NONBLOCK`, a read after a spurious readiness of
+/// [`AsyncFd`] would block the runtime thread.
+fn open_tty() -> io::Result<(AsyncFd<File>, RawMode)> {
+    let tty = File::options()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK | libc::O_NOCTTY)
+        .open("/dev/tty")?;
+    let raw_mode = RawMode::enable(tty.as_raw_fd())?;
+    Ok((AsyncFd::new(tty)?, raw_mode))
+}
+
+/// The columns and rows of the terminal at stdout.
+fn terminal_size() -> io::Result<(u32, u32)> {
+    // SAFETY: `winsize` is plain data, filled by the ioctl.
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    // SAFETY: `TIOCGWINSZ` writes a `winsize` to the pointer.
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((size.ws_col.into(), size.ws_row.into()))
+}
+
+/// The terminal at `fd` in raw mode, restored when dropped.
+struct RawMode {
+    fd: RawFd,
+    original: libc::termios,
+}
+
+impl RawMode {
+    fn enable(fd: RawFd) -> io::Result<Self> {
+        // SAFETY: `termios` is plain data, filled by `tcgetattr`.
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        // SAFETY: the pointers are valid `termios`.
+        unsafe {
+            if libc::tcgetattr(fd, &mut original) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut raw = original;
+            libc::cfmakeraw(&mut raw);
+            if libc::tcsetattr(fd, libc::TCSANOW, &raw) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(Self { fd, original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: `original` is a valid `termios`.
+        unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &self.original) };
+    }
+}
+
+/// Recognizes the escape character at the start of a line in the input.
+#[derive(Debug)]
+struct EscapeFilter {
+    escape_char: Option<u8>,
+    line_start: bool,
+    escaped: bool, // the escape character was typed, the next one decides
+}
+
+impl EscapeFilter {
+    fn new(escape_char: Option<u8>) -> Self {
+        Self {
+            escape_char,
+            line_start: true,
+            escaped: false,
+        }
+    }
+
+    /// The part of `input` to send on, and whether to disconnect.
+    fn push(&mut self, input: &[u8]) -> (Vec<u8>, bool) {
+        let mut data = Vec::with_capacity(input.len());
+        for &byte in input {
+            if self.escaped {
+                self.escaped = false;
+                match byte {
+                    b'.' => return (data, true),
+                    byte if Some(byte) == self.escape_char => {
+                        data.push(byte);
+                        self.line_start = false;
+                        continue;
+                    }
+                    // Not an escape sequence, both are sent.
+                    _ => data.extend(self.escape_char),
+                }
+            } else if self.line_start && Some(byte) == self.escape_char {
+                self.escaped = true;
+                continue;
+            }
+            data.push(byte);
+            self.line_start = matches!(byte, b'\r' | b'\n');
+        }
+        (data, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes() {
+        let mut escape = EscapeFilter::new(Some(b'~'));
+        assert_eq!((b"ls ~/a~.\r".to_vec(), false), escape.push(b"ls ~/a~.\r"));
+        assert_eq!((vec![], false), escape.push(b"~"));
+        assert_eq!((b"~/bin\r".to_vec(), false), escape.push(b"~/bin\r"));
+        assert_eq!((b"~x".to_vec(), false), escape.push(b"~~x"));
+        assert_eq!((b"\r".to_vec(), true), escape.push(b"\r~.ignored"));
+
+        let mut escape = EscapeFilter::new(Some(b'~'));
+        assert_eq!((vec![], true), escape.push(b"~."));
+
+        let mut escape = EscapeFilter::new(None);
+        assert_eq!((b"~.".to_vec(), false), escape.push(b"~."));
+    }
+}
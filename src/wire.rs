@@ -0,0 +1,7 @@
+//! Encoding of the ssh wire format, see RFC 4251 section 5.
+
+/// Appends `data` to `buffer` as ssh `string`, prefixed with its length.
+pub(crate) fn put_string(buffer: &mut Vec<u8>, data: &[u8]) {
+    buffer.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    buffer.extend_from_slice(data);
+}
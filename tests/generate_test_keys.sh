@@ -7,15 +7,21 @@ cd "${0%/*}" || exit 1
 [ -e "server.ed25519" ] || ssh-keygen -t ed25519 -q -f "server.ed25519" -N "" || exit 1
 [ -e "client.ed25519" ] || ssh-keygen -t ed25519 -q -f "client.ed25519" -N "" || exit 1
 [ -e "client.prot.ed25519" ] || ssh-keygen -t ed25519 -q -f "client.prot.ed25519" -N "test" || exit 1
+[ -e "user_ca" ] || ssh-keygen -t ed25519 -q -f "user_ca" -N "" || exit 1
+
+# sign the client key with the user CA, valid for the docker and the local test user
+[ -e "client.ed25519-cert.pub" ] || ssh-keygen -q -s "user_ca" -I "async-ssh2-tokio-test" -n "root,test" "client.ed25519.pub" || exit 1
 
 # copy files into the Dockerfile folders
 cp server.ed25519 sshd-test/ssh_host_ed25519_key
 cp server.ed25519.pub sshd-test/ssh_host_ed25519_key.pub
 cp client.ed25519 async-ssh2-tokio/id_ed25519
 cp client.ed25519.pub async-ssh2-tokio/id_ed25519.pub
+cp client.ed25519-cert.pub async-ssh2-tokio/id_ed25519-cert.pub
 cp client.prot.ed25519 async-ssh2-tokio/prot.id_ed25519
 cp client.prot.ed25519.pub async-ssh2-tokio/prot.id_ed25519.pub
 cp server.ed25519.pub async-ssh2-tokio
+cp user_ca.pub sshd-test
 
 # setup authorized keys
 rm -f authorized_keys
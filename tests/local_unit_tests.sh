@@ -23,6 +23,7 @@ export ASYNC_SSH2_TEST_HOST_PW="test"
 export ASYNC_SSH2_TEST_HOST_USER="test"
 export ASYNC_SSH2_TEST_CLIENT_PRIV="$PWD/client.ed25519"
 export ASYNC_SSH2_TEST_CLIENT_PUB="$PWD/client.ed25519.pub"
+export ASYNC_SSH2_TEST_CLIENT_CERT="$PWD/client.ed25519-cert.pub"
 export ASYNC_SSH2_TEST_CLIENT_PROT_PRIV="$PWD/client.prot.ed25519"
 export ASYNC_SSH2_TEST_CLIENT_PROT_PUB="$PWD/client.prot.ed25519.pub"
 export ASYNC_SSH2_TEST_CLIENT_PROT_PASS="test"
@@ -35,6 +36,7 @@ else
 fi
 
 # make sure client pub key is in authorized keys for test user
+# the certificate tests additionally need "TrustedUserCAKeys $PWD/user_ca.pub" in the sshd config
 
 # use ssh-copy-id for the non-protected one since it will make all folders and files
 sshpass -p "$ASYNC_SSH2_TEST_HOST_PW" ssh-copy-id -o StrictHostKeyChecking=no -p "$ASYNC_SSH2_TEST_HOST_PORT" -i "$ASYNC_SSH2_TEST_CLIENT_PRIV" "$ASYNC_SSH2_TEST_HOST_USER"@"$ASYNC_SSH2_TEST_HOST_IP" || exit 1